@@ -0,0 +1,258 @@
+use crate::parameters::GcdParameters;
+use crate::cache::{self, CacheStatus, ResultCache};
+use crate::form::FieldErrors;
+use crate::history;
+use crate::math::{self, Answer};
+use crate::metrics;
+use crate::ratelimit::{RateLimitStatus, RateLimiter};
+use actix_web::error::{InternalError, JsonPayloadError, UrlencodedError};
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, guard, web};
+use serde::{Deserialize, Serialize};
+
+/// The body of `POST /api/batch`: a list of independent problems, each
+/// written like the body of `POST /api/gcd`.
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    problems: Vec<GcdParameters>,
+}
+
+/// The answer to one problem in a batch: either an `Answer` or an `ApiError`.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum BatchResult {
+    Solved(Answer),
+    Failed(ApiError),
+}
+
+#[derive(Serialize)]
+struct BatchResponse {
+    results: Vec<BatchResult>,
+}
+
+/// The body of `POST /api/bezout`: `{"a": 240, "b": 46}`.
+#[derive(Deserialize)]
+pub struct BezoutRequest {
+    a: u64,
+    b: u64,
+}
+
+/// Bézout coefficients for `a` and `b`: `a * x + b * y == gcd`.
+#[derive(Serialize)]
+struct BezoutResponse {
+    gcd: u64,
+    x: i128,
+    y: i128,
+}
+
+/// The body of `POST /api/inverse`: `{"a": 3, "modulus": 11}`.
+#[derive(Deserialize)]
+pub struct InverseRequest {
+    a: u64,
+    modulus: u64,
+}
+
+#[derive(Serialize)]
+struct InverseResponse {
+    inverse: u64,
+}
+
+/// The body of `GET /status`.
+#[derive(Serialize)]
+struct Status {
+    /// `null` if rate limiting is turned off.
+    rate_limit: Option<RateLimitStatus>,
+    /// `null` if the result cache is turned off.
+    cache: Option<CacheStatus>,
+}
+
+/// The body of every JSON error response: `{"error": "..."}`.
+#[derive(Serialize)]
+pub struct ApiError {
+    pub error: String,
+    /// For a form that didn't validate, what's wrong with each field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<FieldErrors>,
+}
+
+/// `POST /api/gcd`: find the greatest common divisor and least common
+/// multiple of a list of numbers, like `{"numbers": [12, 18]}`. The body may
+/// also ask for just one of them, with `"operation": "gcd"` or `"lcm"`, and
+/// for the steps Euclid's algorithm took, with `"trace": true`.
+pub async fn post_gcd(parameters: web::Json<GcdParameters>, request: HttpRequest) -> HttpResponse {
+    metrics::record_input(&request, &parameters.numbers);
+    gcd_response(&parameters, &request)
+}
+
+/// `POST /api/batch`: solve each problem in the list separately. A problem
+/// we can't solve gets an error in its place in the results, and doesn't
+/// affect the others.
+pub async fn post_batch(batch: web::Json<BatchRequest>, request: HttpRequest) -> HttpResponse {
+    let results = batch
+        .problems
+        .iter()
+        .map(|problem| {
+            metrics::record_input(&request, &problem.numbers);
+            cache::solve(problem, cache::for_request(&request))
+        })
+        .map(|answer| match answer {
+            Ok(answer) => BatchResult::Solved(answer),
+            Err(message) => BatchResult::Failed(ApiError {
+                error: message.to_string(),
+                fields: None,
+            }),
+        })
+        .collect();
+    HttpResponse::Ok().json(BatchResponse { results })
+}
+
+/// `POST /api/bezout`: find the GCD of two numbers along with Bézout
+/// coefficients for them, using the extended Euclidean algorithm.
+pub async fn post_bezout(request: web::Json<BezoutRequest>) -> HttpResponse {
+    let (gcd, x, y) = math::extended_gcd(request.a, request.b);
+    HttpResponse::Ok().json(BezoutResponse { gcd, x, y })
+}
+
+/// `POST /api/inverse`: find the inverse of `a` modulo `modulus`.
+pub async fn post_inverse(request: web::Json<InverseRequest>) -> HttpResponse {
+    match math::mod_inverse(request.a, request.modulus) {
+        Some(inverse) => HttpResponse::Ok().json(InverseResponse { inverse }),
+        None => bad_request(&format!("{} has no inverse modulo {}", request.a, request.modulus)),
+    }
+}
+
+/// `GET /status`: counters describing what the server has been doing.
+pub async fn get_status(
+    limiter: Option<web::Data<RateLimiter>>,
+    cache: Option<web::Data<ResultCache>>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(Status {
+        rate_limit: limiter.map(|limiter| limiter.status()),
+        cache: cache.map(|cache| cache.status()),
+    })
+}
+
+/// Respond with the answer to `parameters` as JSON, or with a JSON error
+/// explaining why there isn't one.
+pub fn gcd_response(parameters: &GcdParameters, request: &HttpRequest) -> HttpResponse {
+    match cache::solve(parameters, cache::for_request(request)) {
+        Ok(answer) => {
+            history::record(request, parameters, &answer);
+            HttpResponse::Ok().json(answer)
+        }
+        Err(message) => bad_request(message),
+    }
+}
+
+pub fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(ApiError {
+        error: message.to_string(),
+        fields: None,
+    })
+}
+
+/// Explain what's wrong with the fields of a form.
+pub fn invalid_form(errors: FieldErrors) -> HttpResponse {
+    HttpResponse::BadRequest().json(ApiError {
+        error: errors.summary(),
+        fields: Some(errors),
+    })
+}
+
+/// Accept JSON bodies of up to `limit` bytes, and report those we can't
+/// parse with a JSON error, rather than actix's plain text one.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default().limit(limit).error_handler(|error, _request| {
+        let response = match error {
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => too_large(),
+            _ => bad_request(&error.to_string()),
+        };
+        InternalError::from_response(error, response).into()
+    })
+}
+
+/// Accept form bodies of up to `limit` bytes, and report those we can't
+/// parse with a JSON error if that's what the client would like.
+pub fn form_config(limit: usize) -> web::FormConfig {
+    web::FormConfig::default().limit(limit).error_handler(|error, request| {
+        if prefers_json(request, false) {
+            let response = match error {
+                UrlencodedError::Overflow { .. } => too_large(),
+                _ => bad_request(&error.to_string()),
+            };
+            InternalError::from_response(error, response).into()
+        } else {
+            error.into()
+        }
+    })
+}
+
+fn too_large() -> HttpResponse {
+    HttpResponse::PayloadTooLarge().json(ApiError {
+        error: "the request is too large".to_string(),
+        fields: None,
+    })
+}
+
+/// A guard matching requests whose body is JSON.
+pub fn json_body() -> impl guard::Guard {
+    guard::fn_guard(|context: &guard::GuardContext| {
+        context
+            .head()
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+    })
+}
+
+/// Decide from the request's `Accept` header whether to answer with JSON
+/// rather than HTML. If the client likes both equally, or doesn't say,
+/// return `default`.
+pub fn prefers_json(request: &HttpRequest, default: bool) -> bool {
+    let accept = match request.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()) {
+        Some(accept) => accept,
+        None => return default,
+    };
+    let json = quality(accept, "application", "json");
+    let html = quality(accept, "text", "html");
+    if json == html { default } else { json > html }
+}
+
+/// How much an `Accept` header value says the client wants `kind/subtype`,
+/// from 0 to 1, using the most specific media range that matches.
+fn quality(accept: &str, kind: &str, subtype: &str) -> f32 {
+    let mut best = (0, 0.0);
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let (k, s) = media.split_once('/').unwrap_or((media, ""));
+        let specificity = if k.eq_ignore_ascii_case(kind) && s.eq_ignore_ascii_case(subtype) {
+            3
+        } else if k.eq_ignore_ascii_case(kind) && s == "*" {
+            2
+        } else if k == "*" && s == "*" {
+            1
+        } else {
+            continue;
+        };
+        if specificity > best.0 {
+            best = (specificity, q);
+        }
+    }
+    best.1
+}
+
+#[test]
+fn test_quality() {
+    let accept = "text/html;q=0.8, application/*;q=0.9, */*;q=0.1";
+    assert_eq!(quality(accept, "application", "json"), 0.9);
+    assert_eq!(quality(accept, "text", "html"), 0.8);
+    assert_eq!(quality(accept, "image", "png"), 0.1);
+    assert_eq!(quality("text/html", "application", "json"), 0.0);
+}
@@ -0,0 +1,182 @@
+//! Ask a running actix-gcd server for the GCD and LCM of some numbers:
+//!
+//!     gcd-client 12 18 --server http://localhost:3000
+
+use actix_gcd::math::{Answer, Operation};
+use actix_gcd::parameters::{GcdParameters, parse_numbers};
+use serde::Deserialize;
+use std::env;
+
+/// What the command line asks for.
+#[derive(Debug)]
+enum Command {
+    Solve { server: String, parameters: GcdParameters },
+    Help,
+}
+
+/// The body of an error response from the server.
+#[derive(Deserialize)]
+struct ApiError {
+    error: String,
+}
+
+fn main() {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "gcd-client".to_string());
+
+    let (server, parameters) = match parse_args(args) {
+        Ok(Command::Solve { server, parameters }) => (server, parameters),
+        Ok(Command::Help) => {
+            print_usage(&program);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try '{} --help' for more information.", program);
+            std::process::exit(2);
+        }
+    };
+
+    match solve(&server, &parameters) {
+        Ok(answer) => print_answer(&answer),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_usage(program: &str) {
+    println!("Usage: {} [OPTIONS] NUMBER...", program);
+    println!();
+    println!("Ask an actix-gcd server for the greatest common divisor and least common");
+    println!("multiple of the numbers, which may be separated by spaces or commas.");
+    println!();
+    println!("Options:");
+    println!("      --server URL             where the server is [default: $ACTIX_GCD_SERVER,");
+    println!("                               or http://localhost:3000]");
+    println!("      --operation gcd|lcm      find only one of them");
+    println!("      --trace                  show each step of Euclid's algorithm");
+    println!("  -h, --help                   print this message");
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut server = env::var("ACTIX_GCD_SERVER")
+        .ok()
+        .filter(|server| !server.is_empty())
+        .unwrap_or_else(|| "http://localhost:3000".to_string());
+    let mut operation = None;
+    let mut trace = false;
+    let mut words = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") && arg != "-h" {
+            words.push(arg);
+            continue;
+        }
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--trace" => trace = true,
+            "--server" | "--operation" => {
+                let Some(value) = inline_value.or_else(|| args.next()) else {
+                    return Err(format!("{} needs a value", flag));
+                };
+                if flag == "--server" {
+                    server = value;
+                } else {
+                    operation = Some(match value.as_str() {
+                        "gcd" => Operation::Gcd,
+                        "lcm" => Operation::Lcm,
+                        _ => return Err(format!("--operation should be 'gcd' or 'lcm', not '{}'", value)),
+                    });
+                }
+            }
+            _ => return Err(format!("unknown flag '{}'", flag)),
+        }
+    }
+
+    let numbers = parse_numbers(&words.join(" ")).map_err(|word| format!("'{}' is not a whole number", word))?;
+    if numbers.is_empty() {
+        return Err("expected at least one number".to_string());
+    }
+    Ok(Command::Solve {
+        server,
+        parameters: GcdParameters { numbers, operation, trace },
+    })
+}
+
+/// Send `parameters` to the server's `/api/gcd`.
+fn solve(server: &str, parameters: &GcdParameters) -> Result<Answer, String> {
+    let url = format!("{}/api/gcd", server.trim_end_matches('/'));
+    // Read the server's own explanation of a 400, rather than just its status.
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let mut response = agent
+        .post(&url)
+        .send_json(parameters)
+        .map_err(|e| format!("can't reach {}: {}", url, e))?;
+
+    let status = response.status();
+    let body = response.body_mut();
+    if status.is_success() {
+        body.read_json::<Answer>()
+            .map_err(|e| format!("can't understand the answer from {}: {}", url, e))
+    } else {
+        match body.read_json::<ApiError>() {
+            Ok(ApiError { error }) => Err(error),
+            Err(_) => Err(format!("{} answered {}", url, status)),
+        }
+    }
+}
+
+fn print_answer(answer: &Answer) {
+    if let Some(gcd) = &answer.gcd {
+        println!("gcd: {}", gcd);
+    }
+    if let Some(lcm) = &answer.lcm {
+        println!("lcm: {}", lcm);
+    }
+    for (i, step) in answer.steps.iter().flatten().enumerate() {
+        println!(
+            "step {}: n = {}, m = {}{}, m % n = {}",
+            i + 1,
+            step.n,
+            step.m,
+            if step.swapped { " (swapped)" } else { "" },
+            step.remainder
+        );
+    }
+}
+
+#[test]
+fn test_parse_args() {
+    let parse = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+
+    let Ok(Command::Solve { server, parameters }) = parse(&["12", "18,30", "--server=http://gcd:8000", "--trace"])
+    else {
+        panic!("should parse");
+    };
+    assert_eq!(server, "http://gcd:8000");
+    assert_eq!(parameters.numbers, [12u32, 18, 30].map(num::BigUint::from));
+    assert_eq!(parameters.operation, None);
+    assert!(parameters.trace);
+
+    let Ok(Command::Solve { parameters, .. }) = parse(&["--operation", "lcm", "4", "6"]) else {
+        panic!("should parse");
+    };
+    assert_eq!(parameters.operation, Some(Operation::Lcm));
+
+    assert!(matches!(parse(&["12", "-h"]), Ok(Command::Help)));
+    assert_eq!(parse(&[]).unwrap_err(), "expected at least one number");
+    assert_eq!(parse(&["12", "x"]).unwrap_err(), "'x' is not a whole number");
+    assert_eq!(parse(&["12", "--server"]).unwrap_err(), "--server needs a value");
+    assert!(parse(&["12", "--operation", "mod"]).is_err());
+    assert!(parse(&["12", "--verbose"]).is_err());
+}
@@ -0,0 +1,170 @@
+use crate::math::{self, Answer, Operation};
+use crate::parameters::GcdParameters;
+use actix_web::{HttpRequest, web};
+use num::BigUint;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The answers to recent problems, shared by all the workers, so that
+/// asking the same question twice doesn't mean working it out twice.
+///
+/// The GCD and LCM don't depend on the order of the numbers or on repeats,
+/// so problems are keyed by their numbers sorted with duplicates removed.
+/// Once the cache is full, the least recently used answer makes way for the
+/// new one.
+pub struct ResultCache {
+    capacity: usize,
+    inner: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    numbers: Vec<BigUint>,
+    operation: Option<Operation>,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<Key, (Answer, u64)>,
+    /// Each entry's key, by when it was last used.
+    by_use: BTreeMap<u64, Key>,
+    clock: u64,
+}
+
+/// What `GET /status` reports about the cache.
+#[derive(Serialize)]
+pub struct CacheStatus {
+    pub capacity: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Key {
+    fn new(numbers: &[BigUint], operation: Option<Operation>) -> Key {
+        let mut numbers = numbers.to_vec();
+        numbers.sort();
+        numbers.dedup();
+        Key { numbers, operation }
+    }
+}
+
+impl Lru {
+    fn get(&mut self, key: &Key) -> Option<Answer> {
+        self.clock += 1;
+        let (answer, used) = self.entries.get_mut(key)?;
+        self.by_use.remove(used);
+        *used = self.clock;
+        self.by_use.insert(self.clock, key.clone());
+        Some(answer.clone())
+    }
+
+    fn insert(&mut self, key: Key, answer: Answer, capacity: usize) {
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.by_use.remove(&used);
+        }
+        while self.entries.len() >= capacity {
+            match self.by_use.pop_first() {
+                Some((_, oldest)) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+        self.by_use.insert(self.clock, key.clone());
+        self.entries.insert(key, (answer, self.clock));
+    }
+}
+
+impl ResultCache {
+    /// A cache holding up to `capacity` answers, which must be at least 1.
+    pub fn new(capacity: usize) -> ResultCache {
+        assert!(capacity > 0);
+        ResultCache {
+            capacity,
+            inner: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The same as `math::solve`, but using the answer from the cache if
+    /// there is one. Steps depend on the order of the numbers, so they
+    /// aren't cached, and are worked out afresh when asked for.
+    pub fn solve(&self, numbers: &[BigUint], operation: Option<Operation>, trace: bool) -> Result<Answer, &'static str> {
+        if numbers.is_empty() {
+            return math::solve(numbers, operation, trace);
+        }
+
+        let steps = if trace { Some(math::steps(numbers)?) } else { None };
+        let key = Key::new(numbers, operation);
+        let cached = self.inner.lock().unwrap().get(&key);
+        let mut answer = match cached {
+            Some(answer) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                answer
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                // Don't hold the lock while we work, since that might take
+                // a while for big numbers.
+                let answer = math::solve(numbers, operation, false)?;
+                self.inner.lock().unwrap().insert(key, answer.clone(), self.capacity);
+                answer
+            }
+        };
+        answer.steps = steps;
+        Ok(answer)
+    }
+
+    pub fn status(&self) -> CacheStatus {
+        CacheStatus {
+            capacity: self.capacity,
+            entries: self.inner.lock().unwrap().entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Work out the answer to `parameters`, or look it up in `cache` if we have
+/// one.
+pub fn solve(parameters: &GcdParameters, cache: Option<&ResultCache>) -> Result<Answer, &'static str> {
+    match cache {
+        Some(cache) => cache.solve(&parameters.numbers, parameters.operation, parameters.trace),
+        None => math::solve(&parameters.numbers, parameters.operation, parameters.trace),
+    }
+}
+
+/// The app's cache, if it has one.
+pub fn for_request(request: &HttpRequest) -> Option<&ResultCache> {
+    request.app_data::<web::Data<ResultCache>>().map(|cache| cache.get_ref())
+}
+
+#[test]
+fn test_cache() {
+    let numbers = |list: &[u32]| list.iter().map(|&n| BigUint::from(n)).collect::<Vec<_>>();
+    let cache = ResultCache::new(2);
+
+    let answer = cache.solve(&numbers(&[12, 18]), None, false).unwrap();
+    assert_eq!(answer.gcd, Some(BigUint::from(6u32)));
+    // The same numbers in another order, repeated, are the same problem...
+    assert_eq!(cache.solve(&numbers(&[18, 12, 18]), None, false), Ok(answer));
+    // ... but asking for only the GCD is a different one.
+    cache.solve(&numbers(&[12, 18]), Some(Operation::Gcd), false).unwrap();
+    let status = cache.status();
+    assert_eq!((status.hits, status.misses, status.entries), (1, 2, 2));
+
+    // Using [12, 18] makes [12, 18] for the GCD the least recently used,
+    // so that's the one to go when [4, 6] arrives.
+    let traced = cache.solve(&numbers(&[18, 12]), None, true).unwrap();
+    assert_eq!(traced.steps.unwrap().len(), 2);
+    cache.solve(&numbers(&[4, 6]), None, false).unwrap();
+    cache.solve(&numbers(&[12, 18]), None, false).unwrap();
+    cache.solve(&numbers(&[12, 18]), Some(Operation::Gcd), false).unwrap();
+    let status = cache.status();
+    assert_eq!((status.hits, status.misses, status.entries), (3, 4, 2));
+}
@@ -0,0 +1,366 @@
+use std::fmt;
+use std::fs;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// How to run the server.
+///
+/// Each setting can come from a command-line flag, an environment variable,
+/// or a TOML config file, in that order of precedence. The environment
+/// variable for `--keep-alive` is `ACTIX_GCD_KEEP_ALIVE`, and so on; in the
+/// config file the keys are the long flag names without the dashes:
+///
+///     bind = ["127.0.0.1:3000", "[::1]:3000"]
+///     workers = 4
+///     keep-alive = 10
+///     tls-cert = "cert.pem"
+///     tls-key = "key.pem"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// The addresses to listen on, like `127.0.0.1:3000`.
+    pub bind: Vec<String>,
+    /// How many worker threads to run; `None` means one per CPU.
+    pub workers: Option<usize>,
+    /// How long to keep an idle connection open, in seconds; zero turns
+    /// keep-alive off.
+    pub keep_alive: u64,
+    /// On SIGTERM, how long to let requests in flight finish before
+    /// dropping them, in seconds.
+    pub shutdown_timeout: u64,
+    /// On SIGTERM, how long to keep serving, while `/readyz` says we're not
+    /// ready, before we stop taking new connections, in seconds. This gives
+    /// a supervisor or load balancer time to send requests elsewhere.
+    pub shutdown_delay: u64,
+    /// The largest request body we accept, in bytes.
+    pub max_request_size: usize,
+    /// If both are set, serve HTTPS using the certificate chain and private
+    /// key in these PEM files.
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    /// How many requests a client may make in a burst, and how many more
+    /// it may make each second after that. A refill of zero turns rate
+    /// limiting off.
+    pub rate_limit_burst: u32,
+    pub rate_limit_refill: u32,
+    /// How many answers to remember, so that repeated problems needn't be
+    /// worked out again; zero turns the cache off.
+    pub cache_size: usize,
+    /// If set, record each problem solved in this file, and show them at
+    /// `/history`.
+    pub history: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: vec!["127.0.0.1:3000".to_string()],
+            workers: None,
+            keep_alive: 5,
+            shutdown_timeout: 30,
+            shutdown_delay: 0,
+            max_request_size: 32 * 1024,
+            tls_cert: None,
+            tls_key: None,
+            rate_limit_burst: 30,
+            rate_limit_refill: 10,
+            cache_size: 1000,
+            history: None,
+        }
+    }
+}
+
+/// The settings, as spelled on the command line.
+const FLAGS: &[&str] = &[
+    "--bind",
+    "--workers",
+    "--keep-alive",
+    "--shutdown-timeout",
+    "--shutdown-delay",
+    "--max-request-size",
+    "--tls-cert",
+    "--tls-key",
+    "--rate-limit-burst",
+    "--rate-limit-refill",
+    "--cache-size",
+    "--history",
+];
+
+/// What the user asked us to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve(Config),
+    Help,
+}
+
+/// Why the configuration could not be understood.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    UnknownFlag(String),
+    MissingValue(String),
+    UnexpectedArgument(String),
+    /// `setting` names where the value came from: a flag, an environment
+    /// variable, or a key in the config file.
+    BadNumber {
+        setting: String,
+        value: String,
+        error: ParseIntError,
+    },
+    OutOfRange {
+        setting: String,
+        value: String,
+        reason: &'static str,
+    },
+    BadFile {
+        file: String,
+        reason: String,
+    },
+    /// Only one of `--tls-cert` and `--tls-key` was given.
+    IncompleteTls,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown option '{}'", flag),
+            ConfigError::MissingValue(flag) => write!(f, "option '{}' needs a value", flag),
+            ConfigError::UnexpectedArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            ConfigError::BadNumber { setting, value, error } => {
+                write!(f, "invalid value '{}' for '{}': {}", value, setting, error)
+            }
+            ConfigError::OutOfRange { setting, value, reason } => {
+                write!(f, "invalid value '{}' for '{}': {}", value, setting, reason)
+            }
+            ConfigError::BadFile { file, reason } => write!(f, "{}: {}", file, reason),
+            ConfigError::IncompleteTls => {
+                f.write_str("'--tls-cert' and '--tls-key' must be given together")
+            }
+        }
+    }
+}
+
+pub fn print_usage(program: &str) {
+    println!("Usage: {} [OPTIONS]", program);
+    println!();
+    println!("Serve a web page and JSON API for computing greatest common divisors.");
+    println!();
+    println!("Options:");
+    println!("      --bind ADDR              address to listen on; repeat or separate with commas");
+    println!("                               for several [default: 127.0.0.1:3000]");
+    println!("      --workers N              worker threads [default: number of CPUs]");
+    println!("      --keep-alive SECONDS     idle connection timeout; 0 disables keep-alive");
+    println!("                               [default: 5]");
+    println!("      --shutdown-timeout SECONDS");
+    println!("                               on SIGTERM, how long to let requests in flight");
+    println!("                               finish [default: 30]");
+    println!("      --shutdown-delay SECONDS on SIGTERM, how long to keep serving while /readyz");
+    println!("                               reports not ready, before that [default: 0]");
+    println!("      --max-request-size BYTES largest request body accepted [default: 32768]");
+    println!("      --tls-cert FILE          serve HTTPS with the certificate chain in this PEM file");
+    println!("      --tls-key FILE           ... and the private key in this one");
+    println!("      --rate-limit-burst N     requests a client may make at once [default: 30]");
+    println!("      --rate-limit-refill N    requests a client may make per second after a burst;");
+    println!("                               0 turns rate limiting off [default: 10]");
+    println!("      --cache-size N           answers to remember; 0 turns caching off [default: 1000]");
+    println!("      --history FILE           keep a history of problems solved in this file");
+    println!("      --config FILE            read settings from a TOML file [default: $ACTIX_GCD_CONFIG]");
+    println!("  -h, --help                   print this message");
+    println!();
+    println!("Each option can also be set with an environment variable, like ACTIX_GCD_WORKERS");
+    println!("for --workers. Flags override the environment, which overrides the config file.");
+}
+
+/// Work out the configuration from the command-line arguments (not
+/// including the program name), the environment variables that `var`
+/// looks up, and the config file if there is one.
+pub fn load<I, V>(args: I, var: V) -> Result<Command, ConfigError>
+where
+    I: IntoIterator<Item = String>,
+    V: Fn(&str) -> Option<String>,
+{
+    let mut flags = Vec::new();
+    let mut file = var("ACTIX_GCD_CONFIG").filter(|file| !file.is_empty());
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            return Err(ConfigError::UnexpectedArgument(arg));
+        }
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        if flag == "-h" || flag == "--help" {
+            return Ok(Command::Help);
+        }
+        if flag != "--config" && !FLAGS.contains(&flag.as_str()) {
+            return Err(ConfigError::UnknownFlag(flag));
+        }
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(ConfigError::MissingValue(flag)),
+        };
+        if flag == "--config" {
+            file = Some(value);
+        } else {
+            flags.push((flag, value));
+        }
+    }
+
+    let mut config = Config::default();
+    if let Some(file) = file {
+        apply_file(&mut config, &file)?;
+    }
+    for flag in FLAGS {
+        let name = format!("ACTIX_GCD_{}", flag[2..].replace('-', "_").to_ascii_uppercase());
+        if let Some(value) = var(&name) {
+            set(&mut config, flag, &name, value)?;
+        }
+    }
+
+    // Several `--bind` flags add up, rather than each replacing the last.
+    let mut bind = Vec::new();
+    for (flag, value) in flags {
+        set(&mut config, &flag, &flag, value)?;
+        if flag == "--bind" {
+            bind.append(&mut config.bind);
+            config.bind = bind.clone();
+        }
+    }
+
+    if config.tls_cert.is_some() != config.tls_key.is_some() {
+        return Err(ConfigError::IncompleteTls);
+    }
+    Ok(Command::Serve(config))
+}
+
+/// Read settings from the TOML file `file`.
+fn apply_file(config: &mut Config, file: &str) -> Result<(), ConfigError> {
+    let bad_file = |reason: String| ConfigError::BadFile {
+        file: file.to_string(),
+        reason,
+    };
+    let text = fs::read_to_string(file).map_err(|e| bad_file(e.to_string()))?;
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| bad_file(e.to_string()))?;
+
+    for (key, value) in table {
+        let flag = format!("--{}", key);
+        if !FLAGS.contains(&flag.as_str()) {
+            return Err(bad_file(format!("unknown key '{}'", key)));
+        }
+        let text = match value {
+            toml::Value::String(s) => s,
+            toml::Value::Integer(n) => n.to_string(),
+            toml::Value::Array(items) if flag == "--bind" => {
+                let addresses: Option<Vec<&str>> = items.iter().map(|item| item.as_str()).collect();
+                match addresses {
+                    Some(addresses) => addresses.join(","),
+                    None => return Err(bad_file("'bind' should be a list of strings".to_string())),
+                }
+            }
+            _ => return Err(bad_file(format!("'{}' should be a string or a number", key))),
+        };
+        let setting = format!("{}: {}", file, key);
+        set(config, &flag, &setting, text)?;
+    }
+    Ok(())
+}
+
+/// Set the field of `config` named by `flag` from the text `value`.
+/// `setting` says where the value came from, for error messages.
+fn set(config: &mut Config, flag: &str, setting: &str, value: String) -> Result<(), ConfigError> {
+    let out_of_range = |reason| ConfigError::OutOfRange {
+        setting: setting.to_string(),
+        value: value.clone(),
+        reason,
+    };
+
+    match flag {
+        "--bind" => {
+            config.bind = value
+                .split(',')
+                .map(|address| address.trim().to_string())
+                .filter(|address| !address.is_empty())
+                .collect();
+            if config.bind.is_empty() {
+                return Err(out_of_range("expected at least one address"));
+            }
+        }
+        "--workers" => {
+            let workers = number(setting, &value)?;
+            if workers == 0 {
+                return Err(out_of_range("must be at least 1"));
+            }
+            config.workers = Some(workers);
+        }
+        "--keep-alive" => config.keep_alive = number(setting, &value)?,
+        "--shutdown-timeout" => config.shutdown_timeout = number(setting, &value)?,
+        "--shutdown-delay" => config.shutdown_delay = number(setting, &value)?,
+        "--max-request-size" => config.max_request_size = number(setting, &value)?,
+        "--tls-cert" => config.tls_cert = Some(value).filter(|file| !file.is_empty()),
+        "--tls-key" => config.tls_key = Some(value).filter(|file| !file.is_empty()),
+        "--rate-limit-burst" => {
+            config.rate_limit_burst = number(setting, &value)?;
+            if config.rate_limit_burst == 0 {
+                return Err(out_of_range("must be at least 1"));
+            }
+        }
+        "--rate-limit-refill" => config.rate_limit_refill = number(setting, &value)?,
+        "--cache-size" => config.cache_size = number(setting, &value)?,
+        "--history" => config.history = Some(value).filter(|file| !file.is_empty()),
+        _ => return Err(ConfigError::UnknownFlag(flag.to_string())),
+    }
+    Ok(())
+}
+
+fn number<T: FromStr<Err = ParseIntError>>(setting: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|error| ConfigError::BadNumber {
+        setting: setting.to_string(),
+        value: value.to_string(),
+        error,
+    })
+}
+
+#[test]
+fn test_load() {
+    let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let no_env = |_: &str| None;
+
+    assert_eq!(load(args(&[]), no_env), Ok(Command::Serve(Config::default())));
+    assert_eq!(load(args(&["--help"]), no_env), Ok(Command::Help));
+
+    let env = |name: &str| match name {
+        "ACTIX_GCD_WORKERS" => Some("2".to_string()),
+        "ACTIX_GCD_BIND" => Some("0.0.0.0:80".to_string()),
+        _ => None,
+    };
+    let config = match load(args(&["--bind", "[::1]:3000", "--bind=127.0.0.1:3000", "--workers", "8"]), env) {
+        Ok(Command::Serve(config)) => config,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(config.bind, ["[::1]:3000", "127.0.0.1:3000"]);
+    assert_eq!(config.workers, Some(8));
+
+    let config = match load(args(&["--keep-alive", "0", "--shutdown-delay=5"]), env) {
+        Ok(Command::Serve(config)) => config,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(config.bind, ["0.0.0.0:80"]);
+    assert_eq!((config.workers, config.keep_alive), (Some(2), 0));
+    assert_eq!((config.shutdown_delay, config.shutdown_timeout), (5, 30));
+}
+
+#[test]
+fn test_load_errors() {
+    let error = |args: &[&str]| {
+        let args = args.iter().map(|a| a.to_string());
+        load(args, |_| None).unwrap_err().to_string()
+    };
+    assert_eq!(error(&["--port", "80"]), "unknown option '--port'");
+    assert_eq!(error(&["--workers"]), "option '--workers' needs a value");
+    assert_eq!(error(&["--workers", "0"]), "invalid value '0' for '--workers': must be at least 1");
+    assert_eq!(error(&["--tls-cert", "cert.pem"]), "'--tls-cert' and '--tls-key' must be given together");
+    assert_eq!(
+        error(&["--keep-alive", "-1"]),
+        "invalid value '-1' for '--keep-alive': invalid digit found in string"
+    );
+}
@@ -0,0 +1,136 @@
+use crate::i18n::Catalog;
+use crate::math::Operation;
+use crate::parameters::{GcdParameters, parse_numbers};
+use askama::Template;
+use serde::{Deserialize, Serialize};
+
+/// The fields of the form on the index page, just as the user filled them
+/// in. Any body that is validly URL-encoded deserializes into this, so that
+/// we can check the values ourselves and explain what's wrong with them.
+#[derive(Default, Deserialize)]
+pub struct GcdForm {
+    #[serde(default)]
+    pub numbers: String,
+    #[serde(default)]
+    pub operation: String,
+    /// Present, with any value, if the box was ticked.
+    #[serde(default)]
+    pub trace: Option<String>,
+}
+
+/// What's wrong with each field of a form, if anything.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct FieldErrors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub numbers: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+}
+
+/// The index page: the form, filled in, with any errors.
+#[derive(Template)]
+#[template(path = "index.html")]
+struct IndexPage<'a> {
+    t: &'a Catalog,
+    form: &'a GcdForm,
+    errors: &'a FieldErrors,
+}
+
+impl FieldErrors {
+    /// All the messages, one after another.
+    pub fn summary(&self) -> String {
+        let messages: Vec<&str> = [&self.numbers, &self.operation]
+            .into_iter()
+            .filter_map(|message| message.as_deref())
+            .collect();
+        messages.join(" ")
+    }
+}
+
+impl GcdForm {
+    /// Refill the form from `parameters`, to show them to the user again.
+    pub fn from_parameters(parameters: &GcdParameters) -> GcdForm {
+        let numbers: Vec<String> = parameters.numbers.iter().map(|n| n.to_string()).collect();
+        GcdForm {
+            numbers: numbers.join(", "),
+            operation: match parameters.operation {
+                Some(Operation::Gcd) => "gcd",
+                Some(Operation::Lcm) => "lcm",
+                None => "",
+            }
+            .to_string(),
+            trace: parameters.trace.then(|| "true".to_string()),
+        }
+    }
+
+    /// Check every field, and either turn the form into parameters for the
+    /// computation or say what's wrong with each field, in the language of
+    /// `t`.
+    pub fn validate(&self, t: &Catalog) -> Result<GcdParameters, FieldErrors> {
+        let mut errors = FieldErrors::default();
+
+        let numbers = match parse_numbers(&self.numbers) {
+            Ok(numbers) if numbers.is_empty() => {
+                errors.numbers = Some(t.get("error.no-numbers").to_string());
+                Vec::new()
+            }
+            Ok(numbers) => numbers,
+            Err(word) => {
+                errors.numbers = Some(t.format("error.not-a-number", &[word]));
+                Vec::new()
+            }
+        };
+
+        let operation = match self.operation.as_str() {
+            "gcd" => Some(Operation::Gcd),
+            "lcm" => Some(Operation::Lcm),
+            "" => None,
+            _ => {
+                errors.operation = Some(t.get("error.operation").to_string());
+                None
+            }
+        };
+
+        if errors != FieldErrors::default() {
+            return Err(errors);
+        }
+        Ok(GcdParameters {
+            numbers,
+            operation,
+            trace: self.trace.is_some(),
+        })
+    }
+
+    /// The index page, showing the values the user entered and any
+    /// `errors` next to the fields they're about.
+    pub fn page<'a>(&'a self, t: &'a Catalog, errors: &'a FieldErrors) -> impl Template + 'a {
+        IndexPage { t, form: self, errors }
+    }
+}
+
+#[test]
+fn test_validate() {
+    let form = |numbers: &str, operation: &str| GcdForm {
+        numbers: numbers.to_string(),
+        operation: operation.to_string(),
+        trace: None,
+    };
+
+    let t = crate::i18n::english();
+    let parameters = form("12, 18 0", "lcm").validate(t).unwrap();
+    assert_eq!(parameters.numbers, [12u32, 18, 0].map(num::BigUint::from));
+    assert_eq!(parameters.operation, Some(Operation::Lcm));
+
+    let errors = form("12, x", "mod").validate(t).unwrap_err();
+    assert_eq!(errors.numbers.as_deref(), Some("'x' is not a whole number."));
+    assert_eq!(errors.operation.as_deref(), Some("Choose GCD or LCM."));
+
+    let errors = form(" , ", "gcd").validate(t).unwrap_err();
+    assert_eq!(errors.numbers.as_deref(), Some("Enter at least one number."));
+    assert_eq!(errors.operation, None);
+
+    let html = form("<b>1</b>", "lcm").page(t, &errors).render().unwrap();
+    assert!(html.contains(r#"value="&#60;b&#62;1&#60;/b&#62;""#));
+    assert!(html.contains(r#"<option value="lcm" selected>"#));
+    assert!(html.contains("Enter at least one number."));
+}
@@ -0,0 +1,109 @@
+use actix_web::{HttpResponse, web};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// What a process supervisor needs to know: whether the server is up, and
+/// whether it wants new requests.
+///
+/// The server is ready from when it starts until it's told to stop; while
+/// it finishes the requests in flight, `/readyz` says it isn't, so that
+/// nothing new gets sent its way.
+pub struct Health {
+    started: Instant,
+    workers: usize,
+    ready: AtomicBool,
+}
+
+/// The body of `GET /healthz`.
+#[derive(Serialize)]
+struct Liveness {
+    status: &'static str,
+    uptime_seconds: u64,
+    workers: usize,
+}
+
+/// The body of `GET /readyz`.
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+}
+
+/// The body of `GET /version`.
+#[derive(Serialize)]
+struct Version {
+    name: &'static str,
+    version: &'static str,
+    /// `debug` or `release`.
+    profile: &'static str,
+}
+
+impl Health {
+    pub fn new(workers: usize) -> Health {
+        Health {
+            started: Instant::now(),
+            workers,
+            ready: AtomicBool::new(true),
+        }
+    }
+
+    /// Say that the server is shutting down.
+    pub fn stopping(&self) {
+        self.ready.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+}
+
+/// `GET /healthz`: answered whenever the server can answer anything at all.
+pub async fn get_healthz(health: web::Data<Health>) -> HttpResponse {
+    HttpResponse::Ok().json(Liveness {
+        status: "ok",
+        uptime_seconds: health.started.elapsed().as_secs(),
+        workers: health.workers,
+    })
+}
+
+/// `GET /readyz`: 200 while the server is taking requests, and 503 once
+/// it's shutting down.
+pub async fn get_readyz(health: web::Data<Health>) -> HttpResponse {
+    let ready = health.is_ready();
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    response.json(Readiness { ready })
+}
+
+/// `GET /version`: which build of the server this is.
+pub async fn get_version() -> HttpResponse {
+    HttpResponse::Ok().json(Version {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+    })
+}
+
+#[actix_web::test]
+async fn test_readyz() {
+    use actix_web::body::MessageBody;
+    use actix_web::http::StatusCode;
+
+    let health = web::Data::new(Health::new(4));
+    let response = get_readyz(health.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.into_body().try_into_bytes().unwrap(), r#"{"ready":true}"#);
+
+    health.stopping();
+    let response = get_readyz(health.clone()).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.into_body().try_into_bytes().unwrap(), r#"{"ready":false}"#);
+
+    let response = get_healthz(health).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().try_into_bytes().unwrap();
+    assert_eq!(body, r#"{"status":"ok","uptime_seconds":0,"workers":4}"#);
+}
@@ -0,0 +1,539 @@
+use crate::api::{self, ApiError};
+use crate::i18n::{self, Catalog};
+use crate::math::{self, Answer, Operation};
+use crate::parameters::{self, GcdParameters};
+use crate::list;
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpRequest, HttpResponse, web};
+use askama::Template;
+use num::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Every problem the server has solved, kept in a file so that it survives
+/// restarts, and in memory so that we can page through it quickly.
+///
+/// The file has a line of JSON for each problem solved, appended as we go.
+/// Deleting entries writes the file afresh without them, so that deleted
+/// numbers don't linger on disk. Reading it from the beginning and replaying
+/// each line gets us back where we were.
+pub struct History {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    path: String,
+    file: File,
+    entries: BTreeMap<u64, Entry>,
+    /// Ids are never reused, even for deleted entries.
+    next_id: u64,
+}
+
+/// One problem, and its answer.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    /// When it was solved, in seconds since the Unix epoch.
+    pub time: u64,
+    #[serde(serialize_with = "math::serialize_numbers", deserialize_with = "parameters::numbers")]
+    pub numbers: Vec<BigUint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<Operation>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "math::serialize_optional",
+        deserialize_with = "parameters::optional_number"
+    )]
+    pub gcd: Option<BigUint>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "math::serialize_optional",
+        deserialize_with = "parameters::optional_number"
+    )]
+    pub lcm: Option<BigUint>,
+}
+
+/// One line of the history file. Files written by earlier versions may
+/// record deletions with `Deleted` and `Cleared` lines, rather than leaving
+/// the entries out.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Entry(Entry),
+    Deleted { deleted: u64 },
+    Cleared { cleared: bool },
+    /// The first line of a file written afresh, so that the ids of deleted
+    /// entries aren't used again.
+    NextId { next_id: u64 },
+}
+
+/// One page of the history, newest first.
+#[derive(Debug, Serialize)]
+pub struct Page {
+    pub entries: Vec<Entry>,
+    pub page: usize,
+    pub per_page: usize,
+    /// How many entries there are on all the pages together.
+    pub total: usize,
+}
+
+/// The query string of `GET /history` and `GET /api/history`, like
+/// `?page=2&per_page=50`. Pages are numbered from 1.
+#[derive(Deserialize)]
+pub struct PageQuery {
+    #[serde(default = "first_page")]
+    page: usize,
+    #[serde(default = "default_per_page")]
+    per_page: usize,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    20
+}
+
+const MAX_PER_PAGE: usize = 100;
+
+impl History {
+    /// Load the history from `path`, creating the file if it doesn't exist.
+    pub fn open(path: &str) -> io::Result<History> {
+        let mut entries = BTreeMap::new();
+        let mut next_id = 1;
+        let mut contents = Vec::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut contents)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        // If the server stopped in the middle of writing a line, the file
+        // ends with part of one. Drop it, so that the next line we write
+        // starts on a line of its own; anything wrong earlier in the file is
+        // still an error.
+        let complete = contents.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if complete < contents.len() {
+            eprintln!("warning: {}: dropping an incomplete last line", path);
+            OpenOptions::new().write(true).open(path)?.set_len(complete as u64)?;
+        }
+        let text = std::str::from_utf8(&contents[..complete])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // How many lines there were for entries, and for deleting them.
+        let mut replayed = 0;
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line = serde_json::from_str(line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, e))
+            })?;
+            match line {
+                Line::Entry(entry) => {
+                    next_id = next_id.max(entry.id + 1);
+                    entries.insert(entry.id, entry);
+                }
+                Line::Deleted { deleted } => {
+                    entries.remove(&deleted);
+                }
+                Line::Cleared { .. } => entries.clear(),
+                Line::NextId { next_id: id } => {
+                    next_id = next_id.max(id);
+                    continue;
+                }
+            }
+            replayed += 1;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut inner = Inner {
+            path: path.to_string(),
+            file,
+            entries,
+            next_id,
+        };
+        // Get rid of anything deleted before, in an older file.
+        if replayed > inner.entries.len() {
+            inner.rewrite()?;
+        }
+        Ok(History {
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Add the problem `numbers`, solved at `time`, to the history, and
+    /// return its id.
+    pub fn record(
+        &self,
+        numbers: &[BigUint],
+        operation: Option<Operation>,
+        answer: &Answer,
+        time: u64,
+    ) -> io::Result<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = Entry {
+            id: inner.next_id,
+            time,
+            numbers: numbers.to_vec(),
+            operation,
+            gcd: answer.gcd.clone(),
+            lcm: answer.lcm.clone(),
+        };
+        inner.append(&Line::Entry(entry.clone()))?;
+        inner.next_id += 1;
+        inner.entries.insert(entry.id, entry);
+        Ok(inner.next_id - 1)
+    }
+
+    /// Delete the entry `id`. Return false if there's no such entry.
+    pub fn delete(&self, id: u64) -> io::Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(entry) = inner.entries.remove(&id) else {
+            return Ok(false);
+        };
+        if let Err(e) = inner.rewrite() {
+            inner.entries.insert(id, entry);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Delete every entry.
+    pub fn clear(&self) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let entries = std::mem::take(&mut inner.entries);
+        if let Err(e) = inner.rewrite() {
+            inner.entries = entries;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn page(&self, page: usize, per_page: usize) -> Page {
+        let page = page.max(1);
+        let per_page = per_page.clamp(1, MAX_PER_PAGE);
+        let inner = self.inner.lock().unwrap();
+        Page {
+            entries: inner
+                .entries
+                .values()
+                .rev()
+                .skip((page - 1).saturating_mul(per_page))
+                .take(per_page)
+                .cloned()
+                .collect(),
+            page,
+            per_page,
+            total: inner.entries.len(),
+        }
+    }
+}
+
+impl Inner {
+    fn append(&mut self, line: &Line) -> io::Result<()> {
+        let mut text = serde_json::to_string(line)?;
+        text.push('\n');
+        self.file.write_all(text.as_bytes())
+    }
+
+    /// Write the file afresh with just the entries we have, into a new file
+    /// that then takes the old one's place, so that a crash part way through
+    /// leaves the old file as it was.
+    fn rewrite(&mut self) -> io::Result<()> {
+        let mut text = serde_json::to_string(&Line::NextId { next_id: self.next_id })?;
+        text.push('\n');
+        for entry in self.entries.values() {
+            text.push_str(&serde_json::to_string(entry)?);
+            text.push('\n');
+        }
+        let temporary = format!("{}.tmp", self.path);
+        let mut file = File::create(&temporary)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Add a problem the server has just solved to the app's history, if it
+/// keeps one. If we can't, say so on standard error, but don't let it spoil
+/// the answer.
+pub fn record(request: &HttpRequest, parameters: &GcdParameters, answer: &Answer) {
+    let Some(history) = request.app_data::<web::Data<History>>() else {
+        return;
+    };
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    if let Err(e) = history.record(&parameters.numbers, parameters.operation, answer, time) {
+        eprintln!("error: can't record history: {}", e);
+    }
+}
+
+/// `GET /history`: a page of the history, as HTML or JSON, whichever the
+/// client prefers.
+pub async fn get_history(
+    history: Option<web::Data<History>>,
+    query: web::Query<PageQuery>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let json = api::prefers_json(&request, false);
+    let Some(history) = history else {
+        return turned_off(json);
+    };
+    let page = history.page(query.page, query.per_page);
+    if json {
+        return HttpResponse::Ok().json(page);
+    }
+    let t = i18n::negotiate(&request);
+    i18n::render(StatusCode::OK, t, &history_page(t, &page))
+}
+
+/// `GET /api/history`: a page of the history, as JSON.
+pub async fn get_history_api(history: Option<web::Data<History>>, query: web::Query<PageQuery>) -> HttpResponse {
+    match history {
+        Some(history) => HttpResponse::Ok().json(history.page(query.page, query.per_page)),
+        None => turned_off(true),
+    }
+}
+
+/// `DELETE /api/history/{id}`
+pub async fn delete_entry(history: Option<web::Data<History>>, id: web::Path<u64>) -> HttpResponse {
+    let Some(history) = history else {
+        return turned_off(true);
+    };
+    match history.delete(*id) {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(ApiError {
+            error: format!("there is no history entry {}", id),
+            fields: None,
+        }),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// `DELETE /api/history`: delete every entry.
+pub async fn delete_all(history: Option<web::Data<History>>) -> HttpResponse {
+    let Some(history) = history else {
+        return turned_off(true);
+    };
+    match history.clear() {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// `POST /history/{id}/delete`, from the delete buttons on the history
+/// page, since HTML forms can't send `DELETE`. Go back to the history.
+pub async fn post_delete(history: Option<web::Data<History>>, id: web::Path<u64>) -> HttpResponse {
+    let Some(history) = history else {
+        return turned_off(false);
+    };
+    match history.delete(*id) {
+        Ok(_) => HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, "/history"))
+            .finish(),
+        Err(e) => storage_error(e),
+    }
+}
+
+fn turned_off(json: bool) -> HttpResponse {
+    let message = "the server isn't keeping a history";
+    if json {
+        HttpResponse::NotFound().json(ApiError {
+            error: message.to_string(),
+            fields: None,
+        })
+    } else {
+        HttpResponse::NotFound()
+            .content_type("text/html")
+            .body(format!("{}.", crate::capitalize(message)))
+    }
+}
+
+fn storage_error(e: io::Error) -> HttpResponse {
+    eprintln!("error: can't update history: {}", e);
+    HttpResponse::InternalServerError().json(ApiError {
+        error: "the history couldn't be saved".to_string(),
+        fields: None,
+    })
+}
+
+/// The history page.
+#[derive(Template)]
+#[template(path = "history.html")]
+struct HistoryPage<'a> {
+    t: &'a Catalog,
+    rows: Vec<Row>,
+    per_page: usize,
+    /// The numbers of the pages before and after this one, if there are
+    /// any.
+    newer: Option<usize>,
+    older: Option<usize>,
+}
+
+/// An `Entry`, ready to show.
+struct Row {
+    id: u64,
+    time: String,
+    numbers: String,
+    gcd: String,
+    lcm: String,
+}
+
+fn history_page<'a>(t: &'a Catalog, page: &Page) -> HistoryPage<'a> {
+    let optional = |n: &Option<BigUint>| n.as_ref().map(|n| n.to_string()).unwrap_or_default();
+    HistoryPage {
+        t,
+        rows: page
+            .entries
+            .iter()
+            .map(|entry| Row {
+                id: entry.id,
+                time: format_time(entry.time),
+                numbers: list(&entry.numbers, t),
+                gcd: optional(&entry.gcd),
+                lcm: optional(&entry.lcm),
+            })
+            .collect(),
+        per_page: page.per_page,
+        newer: (page.page > 1).then(|| page.page - 1),
+        older: page
+            .page
+            .checked_add(1)
+            .filter(|_| page.page.saturating_mul(page.per_page) < page.total),
+    }
+}
+
+/// `seconds` since the Unix epoch as a date and time in UTC, like
+/// `2000-02-29 12:30:00 UTC`.
+fn format_time(seconds: u64) -> String {
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+
+    // Howard Hinnant's `civil_from_days`, which counts in 400-year eras
+    // starting on 1 March, so that leap days come at the end of a year.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+#[test]
+fn test_history() {
+    let path = std::env::temp_dir().join(format!("actix-gcd-history-{}.jsonl", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let numbers = |list: &[u64]| list.iter().map(|&n| BigUint::from(n)).collect::<Vec<_>>();
+    let history = History::open(path).unwrap();
+    for (i, problem) in [[12, 18], [4, 6], [u64::MAX, 2]].iter().enumerate() {
+        let problem = numbers(problem);
+        let answer = math::solve(&problem, None, false).unwrap();
+        assert_eq!(history.record(&problem, None, &answer, 1000 + i as u64).unwrap(), i as u64 + 1);
+    }
+    assert!(history.delete(2).unwrap());
+    assert!(!history.delete(2).unwrap());
+    // Deleting an entry really removes its numbers from the file.
+    let text = std::fs::read_to_string(path).unwrap();
+    assert!(text.contains("[12,18]") && !text.contains("[4,6]"), "{}", text);
+
+    let page = history.page(1, 1);
+    assert_eq!((page.entries.len(), page.total), (1, 2));
+    assert_eq!(page.entries[0].id, 3);
+    assert_eq!(page.entries[0].lcm, Some(BigUint::from(u64::MAX) * 2u32));
+    assert_eq!(history.page(2, 1).entries[0].numbers, numbers(&[12, 18]));
+    assert!(history.page(3, 1).entries.is_empty());
+
+    // Opening the file again gets us back where we were, and doesn't reuse
+    // ids.
+    let before = history.page(1, 10);
+    drop(history);
+    let history = History::open(path).unwrap();
+    assert_eq!(history.page(1, 10).entries, before.entries);
+    history.clear().unwrap();
+    assert!(!std::fs::read_to_string(path).unwrap().contains("numbers"));
+    drop(history);
+    let history = History::open(path).unwrap();
+    let answer = math::solve(&numbers(&[1]), None, false).unwrap();
+    assert_eq!(history.record(&numbers(&[1]), None, &answer, 2000).unwrap(), 4);
+    drop(history);
+    assert_eq!(History::open(path).unwrap().page(1, 10).total, 1);
+
+    // A crash in the middle of writing a line leaves part of it at the end
+    // of the file, which is dropped...
+    let complete = std::fs::read_to_string(path).unwrap();
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(br#"{"id":5,"time":3000,"numb"#).unwrap();
+    drop(file);
+    let history = History::open(path).unwrap();
+    assert_eq!(history.page(1, 10).total, 1);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), complete);
+    assert_eq!(history.record(&numbers(&[2]), None, &answer, 3000).unwrap(), 5);
+    drop(history);
+    assert_eq!(History::open(path).unwrap().page(1, 10).total, 2);
+
+    // A file from before deletions were written afresh is tidied up when
+    // it's opened.
+    let old = format!("{}{}\n{}\n", complete, r#"{"deleted":4}"#, r#"{"id":6,"time":4000,"numbers":[7]}"#);
+    std::fs::write(path, old).unwrap();
+    let history = History::open(path).unwrap();
+    assert_eq!(history.page(1, 10).entries.iter().map(|entry| entry.id).collect::<Vec<_>>(), [6]);
+    assert!(!std::fs::read_to_string(path).unwrap().contains(r#""id":4"#));
+    drop(history);
+
+    // ... but a bad line anywhere else is an error.
+    std::fs::write(path, format!("{{\"id\":1,\"time\nbad\n{}", complete)).unwrap();
+    assert_eq!(History::open(path).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_history_page() {
+    let t = i18n::english();
+    let page = |page, total| Page {
+        entries: Vec::new(),
+        page,
+        per_page: 20,
+        total,
+    };
+    let links = |view: HistoryPage| (view.newer, view.older);
+    assert_eq!(links(history_page(t, &page(1, 20))), (None, None));
+    assert_eq!(links(history_page(t, &page(1, 21))), (None, Some(2)));
+    assert_eq!(links(history_page(t, &page(2, 50))), (Some(1), Some(3)));
+
+    // The page number comes from the query string, so it can be anything.
+    assert_eq!(links(history_page(t, &page(usize::MAX, 50))), (Some(usize::MAX - 1), None));
+    assert_eq!(links(history_page(t, &page(usize::MAX / 10, usize::MAX))), (Some(usize::MAX / 10 - 1), None));
+}
+
+#[test]
+fn test_format_time() {
+    assert_eq!(format_time(0), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_time(951_827_400), "2000-02-29 12:30:00 UTC");
+    assert_eq!(format_time(1_709_251_199), "2024-02-29 23:59:59 UTC");
+}
@@ -0,0 +1,152 @@
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpRequest, HttpResponse};
+use askama::Template;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// The messages shown on the HTML pages, in one language.
+///
+/// The catalogs are TOML files in `locales/`, built into the program. A
+/// message's key is its path through the tables, like `gcd.title`.
+pub struct Catalog {
+    /// A language tag, like `en`.
+    pub locale: &'static str,
+    messages: HashMap<String, String>,
+}
+
+/// English comes first, and is the fallback for everything else.
+const SOURCES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.toml")),
+    ("fr", include_str!("../locales/fr.toml")),
+];
+
+static CATALOGS: LazyLock<Vec<Catalog>> = LazyLock::new(|| {
+    SOURCES
+        .iter()
+        .map(|&(locale, text)| Catalog::parse(locale, text))
+        .collect()
+});
+
+impl Catalog {
+    fn parse(locale: &'static str, text: &str) -> Catalog {
+        /// Add the strings in `table` to `messages`. If something else is
+        /// there, return its key.
+        fn flatten(prefix: &str, table: toml::Table, messages: &mut HashMap<String, String>) -> Result<(), String> {
+            for (key, value) in table {
+                let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                match value {
+                    toml::Value::String(message) => {
+                        messages.insert(key, message);
+                    }
+                    toml::Value::Table(table) => flatten(&key, table, messages)?,
+                    _ => return Err(key),
+                }
+            }
+            Ok(())
+        }
+
+        let table = text
+            .parse()
+            .unwrap_or_else(|e| panic!("locales/{}.toml: {}", locale, e));
+        let mut messages = HashMap::new();
+        if let Err(key) = flatten("", table, &mut messages) {
+            panic!("locales/{}.toml: '{}' should be a string", locale, key);
+        }
+        Catalog { locale, messages }
+    }
+
+    /// The message `key`. If this catalog doesn't have it, use the English
+    /// one; if that doesn't either, show the key, so that it's easy to spot.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.messages
+            .get(key)
+            .or_else(|| english().messages.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// The message `key`, with `{0}` replaced by `args[0]`, and so on.
+    pub fn format(&self, key: &str, args: &[&str]) -> String {
+        let mut message = self.get(key).to_string();
+        for (i, arg) in args.iter().enumerate() {
+            message = message.replace(&format!("{{{}}}", i), arg);
+        }
+        message
+    }
+}
+
+pub fn english() -> &'static Catalog {
+    &CATALOGS[0]
+}
+
+/// The catalog the client would like best, going by its `Accept-Language`
+/// header, or English if we have none of the languages it asks for.
+pub fn negotiate(request: &HttpRequest) -> &'static Catalog {
+    request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(english(), choose)
+}
+
+fn choose(accept_language: &str) -> &'static Catalog {
+    let mut best = (0.0, english());
+    for range in accept_language.split(',') {
+        let mut parts = range.split(';');
+        let tag = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        // We only have one catalog per language, so `fr-CA` gets `fr`.
+        let language = tag.split('-').next().unwrap_or("");
+        let catalog = match CATALOGS.iter().find(|c| c.locale.eq_ignore_ascii_case(language)) {
+            Some(catalog) => catalog,
+            None if language == "*" => english(),
+            None => continue,
+        };
+        if q > best.0 {
+            best = (q, catalog);
+        }
+    }
+    best.1
+}
+
+/// Render `page` as an HTML response in the language of `t`.
+pub fn render(status: StatusCode, t: &Catalog, page: &impl Template) -> HttpResponse {
+    match page.render() {
+        Ok(html) => HttpResponse::build(status)
+            .content_type("text/html")
+            .insert_header((header::CONTENT_LANGUAGE, t.locale))
+            .insert_header((header::VARY, "Accept-Language"))
+            .body(html),
+        Err(e) => {
+            eprintln!("error: can't render page: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[test]
+fn test_catalogs() {
+    let english = english();
+    for catalog in CATALOGS.iter() {
+        let mut missing: Vec<&String> = english.messages.keys().filter(|k| !catalog.messages.contains_key(*k)).collect();
+        missing.sort();
+        assert!(missing.is_empty(), "locales/{}.toml is missing {:?}", catalog.locale, missing);
+        for key in catalog.messages.keys() {
+            assert!(english.messages.contains_key(key), "locales/{}.toml has extra key {}", catalog.locale, key);
+        }
+    }
+
+    assert_eq!(english.format("error.not-a-number", &["x"]), "'x' is not a whole number.");
+    assert_eq!(english.get("no.such.key"), "no.such.key");
+}
+
+#[test]
+fn test_choose() {
+    assert_eq!(choose("fr-CH, fr;q=0.9, en;q=0.8").locale, "fr");
+    assert_eq!(choose("de, en;q=0.5, fr;q=0.7").locale, "fr");
+    assert_eq!(choose("de, *;q=0.5").locale, "en");
+    assert_eq!(choose("de").locale, "en");
+    assert_eq!(choose("EN-gb, fr").locale, "en");
+}
@@ -0,0 +1,5 @@
+//! The parts of actix-gcd that `gcd-client` shares with the server: the
+//! arithmetic, and the shape of the requests and answers.
+
+pub mod math;
+pub mod parameters;
@@ -1,66 +1,308 @@
-use actix_web::{App, HttpResponse, HttpServer, web};
-use serde::Deserialize;
+mod api;
+mod cache;
+mod config;
+mod form;
+mod health;
+mod history;
+mod i18n;
+mod metrics;
+mod openapi;
+mod progress;
+mod ratelimit;
+mod theory;
+mod tls;
+mod tools;
+
+#[cfg(test)]
+mod tests;
+
+use actix_gcd::{math, parameters};
+use actix_web::http::{KeepAlive, StatusCode};
+use actix_web::dev::ServerHandle;
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, middleware, web};
+use askama::Template;
+use cache::ResultCache;
+use config::{Command, Config};
+use form::{FieldErrors, GcdForm};
+use health::Health;
+use history::History;
+use i18n::Catalog;
+use math::Step;
+use metrics::Metrics;
+use ratelimit::RateLimiter;
+use num::BigUint;
+use parameters::GcdParameters;
+use std::env;
+use std::io;
+use std::time::Duration;
+
+// To run: cargo run
+// To see the configuration options: cargo run -- --help
 
-#[derive(Deserialize)]
-struct GcdParameters {
-    n: u64,
-    m: u64,
-}
 fn main() {
-    let server = HttpServer::new(|| {
-        App::new()
-            .route("/", web::get().to(get_index))
-            .route("/gcd", web::post().to(post_gcd))
-    });
-
-    println!("Serving on http://localhost:3000...");
-
-    server
-        .bind("127.0.0.1:3000")
-        .expect("error binding server to address")
-        .run()
-        .expect("error running server");
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "actix-gcd".to_string());
+
+    let config = match config::load(args, |name| env::var(name).ok()) {
+        Ok(Command::Serve(config)) => config,
+        Ok(Command::Help) => {
+            config::print_usage(&program);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try '{} --help' for more information.", program);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = actix_web::rt::System::new().block_on(serve(config)) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Run the server until it's told to stop.
+///
+/// On SIGTERM or Ctrl-C, `/readyz` starts reporting that the server isn't
+/// ready. After `config.shutdown_delay` seconds, the server stops accepting
+/// connections and gives the requests in flight up to
+/// `config.shutdown_timeout` seconds to finish.
+async fn serve(config: Config) -> io::Result<()> {
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::load(cert, key)?),
+        _ => None,
+    };
+
+    // Every worker gets its own `App`, but they all share one rate limiter,
+    // one result cache, one history, one set of metrics and one record of
+    // the server's health.
+    let limiter = (config.rate_limit_refill > 0)
+        .then(|| web::Data::new(RateLimiter::new(config.rate_limit_burst, config.rate_limit_refill)));
+    let cache = (config.cache_size > 0).then(|| web::Data::new(ResultCache::new(config.cache_size)));
+    let history = match &config.history {
+        Some(file) => Some(web::Data::new(History::open(file).map_err(|e| {
+            io::Error::new(e.kind(), format!("can't read history from {}: {}", file, e))
+        })?)),
+        None => None,
+    };
+    let metrics = web::Data::new(Metrics::default());
+    let workers = config
+        .workers
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let health = web::Data::new(Health::new(workers));
+
+    let max_request_size = config.max_request_size;
+    let app_health = health.clone();
+    let mut server = HttpServer::new(move || {
+        let mut app = App::new().app_data(metrics.clone()).app_data(app_health.clone());
+        if let Some(limiter) = &limiter {
+            app = app.app_data(limiter.clone());
+        }
+        if let Some(cache) = &cache {
+            app = app.app_data(cache.clone());
+        }
+        if let Some(history) = &history {
+            app = app.app_data(history.clone());
+        }
+        // The last middleware wrapped runs first, so requests the rate
+        // limiter turns away still get counted and logged.
+        app.wrap(middleware::from_fn(ratelimit::limit))
+            .wrap(middleware::from_fn(metrics::track))
+            .configure(|routes| configure(routes, max_request_size))
+    })
+    .keep_alive(match config.keep_alive {
+        0 => KeepAlive::Disabled,
+        seconds => KeepAlive::Timeout(Duration::from_secs(seconds)),
+    })
+    .shutdown_timeout(config.shutdown_timeout)
+    .workers(workers)
+    // We handle the signals ourselves, to mark the server as not ready first.
+    .disable_signals();
+
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    for address in &config.bind {
+        let bound = match &tls {
+            Some(tls) => server.bind_rustls_0_23(address, tls.clone()),
+            None => server.bind(address),
+        };
+        server = bound.map_err(|e| io::Error::new(e.kind(), format!("can't listen on {}: {}", address, e)))?;
+        println!("Serving on {}://{}...", scheme, address);
+    }
+
+    let server = server.run();
+    let delay = Duration::from_secs(config.shutdown_delay);
+    actix_web::rt::spawn(stop_on_signal(server.handle(), health, delay));
+    server.await
+}
+
+/// Wait for SIGTERM or Ctrl-C, then stop `server` gracefully, `delay` after
+/// saying we're no longer ready.
+async fn stop_on_signal(server: ServerHandle, health: web::Data<Health>, delay: Duration) {
+    if let Err(e) = shutdown_signal().await {
+        eprintln!("error: can't listen for signals: {}", e);
+        return;
+    }
+    println!("Shutting down...");
+    health.stopping();
+    actix_web::rt::time::sleep(delay).await;
+    server.stop(true).await;
 }
 
-fn get_index() -> HttpResponse {
-    HttpResponse::Ok().content_type("text/html").body(
-        r#"
-                <title>GCD Calculator</title>
-                <form action="/gcd" method="post">
-                <input type="text" name="n"/>
-                <input type="text" name="m"/>
-                <button type="submit">Compute GCD</button>
-                </form>
-            "#,
-    )
+#[cfg(unix)]
+async fn shutdown_signal() -> io::Result<()> {
+    use actix_web::rt::signal::{self, unix};
+    use std::future::{Future, poll_fn};
+    use std::pin::pin;
+    use std::task::Poll;
+
+    let mut terminate = unix::signal(unix::SignalKind::terminate())?;
+    let mut interrupt = pin!(signal::ctrl_c());
+    let mut terminated = pin!(terminate.recv());
+    poll_fn(|cx| {
+        if let Poll::Ready(result) = interrupt.as_mut().poll(cx) {
+            return Poll::Ready(result);
+        }
+        terminated.as_mut().poll(cx).map(|_| Ok(()))
+    })
+    .await
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> io::Result<()> {
+    actix_web::rt::signal::ctrl_c().await
+}
+
+/// Add the routes to an `App`, accepting request bodies of up to
+/// `max_request_size` bytes.
+fn configure(routes: &mut web::ServiceConfig, max_request_size: usize) {
+    routes
+        .app_data(api::json_config(max_request_size))
+        .app_data(api::form_config(max_request_size))
+        .route("/", web::get().to(get_index))
+        .service(
+            web::resource("/gcd")
+                .route(web::post().guard(api::json_body()).to(post_gcd_json))
+                .route(web::post().to(post_gcd)),
+        )
+        .route("/api/gcd", web::post().to(api::post_gcd))
+        .route("/api/batch", web::post().to(api::post_batch))
+        .route("/api/bezout", web::post().to(api::post_bezout))
+        .route("/api/inverse", web::post().to(api::post_inverse))
+        .route("/status", web::get().to(api::get_status))
+        .route("/metrics", web::get().to(metrics::get_metrics))
+        .route("/openapi.json", web::get().to(openapi::get_openapi))
+        .route("/healthz", web::get().to(health::get_healthz))
+        .route("/readyz", web::get().to(health::get_readyz))
+        .route("/version", web::get().to(health::get_version))
+        .route("/progress", web::get().to(progress::get_page))
+        .route(
+            "/progress/socket",
+            web::get().to(move |request: HttpRequest, body: web::Payload| {
+                progress::get_socket(request, body, max_request_size)
+            }),
+        )
+        .route("/history", web::get().to(history::get_history))
+        .route("/history/{id}/delete", web::post().to(history::post_delete))
+        .service(
+            web::resource("/api/history")
+                .route(web::get().to(history::get_history_api))
+                .route(web::delete().to(history::delete_all)),
+        )
+        .route("/api/history/{id}", web::delete().to(history::delete_entry))
+        .configure(tools::configure);
 }
 
-fn post_gcd(form: web::Form<GcdParameters>) -> HttpResponse {
-    if form.m == 0 || form.n == 0 {
-        return HttpResponse::BadRequest()
-            .content_type("text/html")
-            .body("Computing the GCD with zero is boring.");
+async fn get_index(request: HttpRequest) -> HttpResponse {
+    let t = i18n::negotiate(&request);
+    i18n::render(StatusCode::OK, t, &GcdForm::default().page(t, &FieldErrors::default()))
+}
+
+async fn post_gcd(form: web::Form<GcdForm>, request: HttpRequest) -> HttpResponse {
+    let t = i18n::negotiate(&request);
+    match form.validate(t) {
+        Ok(parameters) => respond(&parameters, &request, false),
+        Err(errors) if api::prefers_json(&request, false) => api::invalid_form(errors),
+        Err(errors) => i18n::render(StatusCode::BAD_REQUEST, t, &form.page(t, &errors)),
     }
+}
 
-    let response = format!(
-        "The greatest common divisor of the numbers {} and {} is <b>{} </b>\n",
-        form.n,
-        form.m,
-        gcd(form.n, form.m)
-    );
+/// `POST /gcd` with a JSON body, like `{"numbers": [12, 18]}`. Unless the
+/// client asks for HTML, the answer is JSON too.
+async fn post_gcd_json(parameters: web::Json<GcdParameters>, request: HttpRequest) -> HttpResponse {
+    respond(&parameters, &request, true)
+}
 
-    HttpResponse::Ok().content_type("text/html").body(response)
+/// The page showing the answer to a problem.
+#[derive(Template)]
+#[template(path = "answer.html")]
+struct AnswerPage<'a> {
+    t: &'a Catalog,
+    /// Each sentence, and the number it ends with.
+    lines: Vec<(String, &'a BigUint)>,
+    steps: Option<&'a [Step]>,
 }
 
-fn gcd(mut n: u64, mut m: u64) -> u64 {
-    assert!(m != 0 && n != 0);
-    while m != 0 {
-        if m < n {
-            let t = m;
-            m = n;
-            n = t;
+/// Answer `parameters` with JSON or HTML, whichever the client prefers; if
+/// it doesn't say, use JSON if `default_json` is true.
+fn respond(parameters: &GcdParameters, request: &HttpRequest, default_json: bool) -> HttpResponse {
+    metrics::record_input(request, &parameters.numbers);
+    if api::prefers_json(request, default_json) {
+        return api::gcd_response(parameters, request);
+    }
+
+    let t = i18n::negotiate(request);
+    let answer = match cache::solve(parameters, cache::for_request(request)) {
+        Ok(answer) => answer,
+        Err(message) => {
+            let errors = FieldErrors {
+                numbers: Some(format!("{}.", capitalize(message))),
+                ..FieldErrors::default()
+            };
+            let form = GcdForm::from_parameters(parameters);
+            return i18n::render(StatusCode::BAD_REQUEST, t, &form.page(t, &errors));
         }
-        m = m % n;
+    };
+    history::record(request, parameters, &answer);
+
+    let numbers = list(&parameters.numbers, t);
+    let mut lines = Vec::new();
+    if let Some(gcd) = &answer.gcd {
+        lines.push((t.format("gcd.answer-gcd", &[&numbers]), gcd));
+    }
+    if let Some(lcm) = &answer.lcm {
+        lines.push((t.format("gcd.answer-lcm", &[&numbers]), lcm));
+    }
+    let page = AnswerPage {
+        t,
+        lines,
+        steps: answer.steps.as_deref(),
+    };
+    i18n::render(StatusCode::OK, t, &page)
+}
+
+/// Write `numbers` as a list in the language of `t`: "12, 18 and 30".
+fn list(numbers: &[BigUint], t: &Catalog) -> String {
+    let texts: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+    match texts.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} {} {}", rest.join(", "), t.get("and"), last),
+        _ => texts.join(""),
     }
-    n
+}
+
+fn capitalize(message: &str) -> String {
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[test]
+fn test_list() {
+    let numbers: Vec<BigUint> = [12u32, 18, 30].iter().map(|&n| n.into()).collect();
+    let t = i18n::english();
+    assert_eq!(list(&numbers[..1], t), "12");
+    assert_eq!(list(&numbers[..2], t), "12 and 18");
+    assert_eq!(list(&numbers, t), "12, 18 and 30");
 }
@@ -0,0 +1,339 @@
+use crate::parameters;
+use num::{BigUint, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// What to compute from a list of numbers.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Gcd,
+    Lcm,
+}
+
+/// The greatest common divisor and least common multiple of a list of
+/// numbers, or whichever of them was asked for.
+///
+/// In JSON, each is a number if it fits in a `u64`, and a string of decimal
+/// digits if not, since many JSON parsers can't handle bigger numbers.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Answer {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional",
+        deserialize_with = "parameters::optional_number"
+    )]
+    pub gcd: Option<BigUint>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional",
+        deserialize_with = "parameters::optional_number"
+    )]
+    pub lcm: Option<BigUint>,
+    /// Each step Euclid's algorithm took to find the GCD, if asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<Step>>,
+}
+
+/// One time around the loop in `gcd`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Step {
+    /// The two numbers, after swapping them if need be so that `n <= m`.
+    #[serde(serialize_with = "serialize_number", deserialize_with = "parameters::number")]
+    pub n: BigUint,
+    #[serde(serialize_with = "serialize_number", deserialize_with = "parameters::number")]
+    pub m: BigUint,
+    /// Whether `n` and `m` had to be swapped.
+    pub swapped: bool,
+    /// `m % n`, which becomes the next `m`.
+    #[serde(serialize_with = "serialize_number", deserialize_with = "parameters::number")]
+    pub remainder: BigUint,
+}
+
+/// Serialize `number` as a JSON number if it fits in a `u64`, or a string
+/// if not.
+pub fn serialize_number<S: Serializer>(number: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+    match number.to_u64() {
+        Some(small) => serializer.serialize_u64(small),
+        None => serializer.serialize_str(&number.to_string()),
+    }
+}
+
+pub fn serialize_optional<S: Serializer>(number: &Option<BigUint>, serializer: S) -> Result<S::Ok, S::Error> {
+    match number {
+        Some(number) => serialize_number(number, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serialize each of `numbers` as `serialize_number` would.
+pub fn serialize_numbers<S: Serializer>(numbers: &[BigUint], serializer: S) -> Result<S::Ok, S::Error> {
+    struct Element<'a>(&'a BigUint);
+
+    impl Serialize for Element<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize_number(self.0, serializer)
+        }
+    }
+
+    serializer.collect_seq(numbers.iter().map(Element))
+}
+
+/// The most decimal digits a number may have for `steps` to trace it.
+/// Every step holds copies of both numbers, so a trace of big numbers takes
+/// a lot of memory, and makes for a page nobody would read anyway.
+pub const MAX_TRACE_DIGITS: u32 = 100;
+
+/// The most steps `steps` will record, however many numbers there are.
+pub const MAX_TRACE_STEPS: usize = 1000;
+
+/// Carry out `operation` on `numbers`; with no operation, find both the GCD
+/// and the LCM. If `trace` is true, also record the steps taken to find the
+/// GCD, within the limits `steps` sets.
+pub fn solve(numbers: &[BigUint], operation: Option<Operation>, trace: bool) -> Result<Answer, &'static str> {
+    if numbers.is_empty() {
+        return Err("expected at least one number");
+    }
+
+    let steps = if trace { Some(steps(numbers)?) } else { None };
+    let wants = |op| operation.is_none() || operation == Some(op);
+    Ok(Answer {
+        gcd: wants(Operation::Gcd).then(|| gcd_all(numbers)),
+        lcm: wants(Operation::Lcm).then(|| lcm_all(numbers)),
+        steps,
+    })
+}
+
+/// Find the GCD of `numbers` the slow way, with `gcd`'s algorithm on
+/// `BigUint`s, and return each step it took. With more than two numbers,
+/// this finds the GCD of the first two, then of that and the third, and so
+/// on.
+///
+/// A number with more than `MAX_TRACE_DIGITS` digits, or a trace that would
+/// take more than `MAX_TRACE_STEPS` steps, is an error.
+pub fn steps(numbers: &[BigUint]) -> Result<Vec<Step>, &'static str> {
+    let limit = BigUint::from(10u32).pow(MAX_TRACE_DIGITS);
+    if numbers.iter().any(|n| *n >= limit) {
+        return Err("only numbers of up to 100 digits can be traced");
+    }
+    let mut steps = Vec::new();
+    let finished = each_step(numbers, |step| {
+        steps.push(step);
+        steps.len() <= MAX_TRACE_STEPS
+    });
+    match finished {
+        Some(_) => Ok(steps),
+        None => Err("tracing these numbers would take more than 1000 steps"),
+    }
+}
+
+/// Take the same steps as `steps`, calling `taken` with each as we go. If
+/// `taken` returns false, stop. Return the GCD, or `None` if we stopped
+/// early.
+pub fn each_step(numbers: &[BigUint], mut taken: impl FnMut(Step) -> bool) -> Option<BigUint> {
+    let mut d = numbers[0].clone();
+    for m in &numbers[1..] {
+        let mut n = d;
+        let mut m = m.clone();
+        if n.is_zero() {
+            d = m;
+            continue;
+        }
+        while !m.is_zero() {
+            let swapped = m < n;
+            if swapped {
+                std::mem::swap(&mut m, &mut n);
+            }
+            let remainder = &m % &n;
+            let step = Step {
+                n: n.clone(),
+                m: std::mem::replace(&mut m, remainder.clone()),
+                swapped,
+                remainder,
+            };
+            if !taken(step) {
+                return None;
+            }
+        }
+        d = n;
+    }
+    Some(d)
+}
+
+/// Every number divides zero, so the GCD of zero and `n` is `n`, and the
+/// GCD of zero and zero is zero.
+pub fn gcd(mut n: u64, mut m: u64) -> u64 {
+    if n == 0 {
+        return m;
+    }
+    while m != 0 {
+        if m < n {
+            std::mem::swap(&mut m, &mut n);
+        }
+        m %= n;
+    }
+    n
+}
+
+/// The least common multiple of `n` and `m`, or `None` if it doesn't fit in
+/// a `u64`. The only multiple of zero is zero, so if either is zero, so is
+/// the LCM.
+pub fn lcm(n: u64, m: u64) -> Option<u64> {
+    if n == 0 || m == 0 {
+        return Some(0);
+    }
+    (n / gcd(n, m)).checked_mul(m)
+}
+
+/// The same algorithm as `gcd`, for numbers of any size. Once both numbers
+/// fit in a `u64`, it finishes the job with `gcd`, which is much faster.
+pub fn big_gcd(mut n: BigUint, mut m: BigUint) -> BigUint {
+    if n.is_zero() {
+        return m;
+    }
+    while !m.is_zero() {
+        if let (Some(a), Some(b)) = (n.to_u64(), m.to_u64()) {
+            return gcd(a, b).into();
+        }
+        if m < n {
+            std::mem::swap(&mut m, &mut n);
+        }
+        m %= &n;
+    }
+    n
+}
+
+/// Return `(g, x, y)` such that `g` is the greatest common divisor of `a` and
+/// `b`, and `a * x + b * y == g`.
+///
+/// The coefficients are never larger in magnitude than `a` or `b`, so they
+/// always fit in an `i128`, as do the products in the loop.
+pub fn extended_gcd(a: u64, b: u64) -> (u64, i128, i128) {
+    let (mut old_r, mut r) = (a as i128, b as i128);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_x, x) = (x, old_x - q * x);
+        (old_y, y) = (y, old_y - q * y);
+    }
+    (old_r as u64, old_x, old_y)
+}
+
+/// The number `x` from 0 to `modulus - 1` such that `a * x` is 1 modulo
+/// `modulus`, if there is one. There is exactly when `a` and `modulus` are
+/// coprime.
+pub fn mod_inverse(a: u64, modulus: u64) -> Option<u64> {
+    if modulus == 0 {
+        return None;
+    }
+    let (g, x, _) = extended_gcd(a % modulus, modulus);
+    if g != 1 {
+        return None;
+    }
+    Some(x.rem_euclid(modulus as i128) as u64)
+}
+
+/// `numbers` as `u64` values, if they all fit.
+fn small(numbers: &[BigUint]) -> Option<Vec<u64>> {
+    numbers.iter().map(ToPrimitive::to_u64).collect()
+}
+
+fn gcd_all(numbers: &[BigUint]) -> BigUint {
+    match small(numbers) {
+        Some(small) => small[1..].iter().fold(small[0], |d, &n| gcd(d, n)).into(),
+        None => numbers[1..].iter().fold(numbers[0].clone(), |d, n| big_gcd(d, n.clone())),
+    }
+}
+
+fn lcm_all(numbers: &[BigUint]) -> BigUint {
+    if let Some(lcm) = small(numbers).and_then(|small| small[1..].iter().try_fold(small[0], |l, &n| lcm(l, n))) {
+        return lcm.into();
+    }
+    numbers[1..].iter().fold(numbers[0].clone(), |l, n| {
+        if l.is_zero() || n.is_zero() {
+            return BigUint::zero();
+        }
+        let d = big_gcd(l.clone(), n.clone());
+        l / d * n
+    })
+}
+
+#[test]
+fn test_solve() {
+    let numbers = |ns: &[u64]| ns.iter().map(|&n| BigUint::from(n)).collect::<Vec<_>>();
+    let both = |gcd: u64, lcm: u64| {
+        Ok(Answer {
+            gcd: Some(gcd.into()),
+            lcm: Some(lcm.into()),
+            steps: None,
+        })
+    };
+    assert_eq!(solve(&numbers(&[12, 18]), None, false), both(6, 36));
+    assert_eq!(solve(&numbers(&[4, 6, 10]), None, false), both(2, 60));
+    assert_eq!(solve(&numbers(&[7]), None, false), both(7, 7));
+    assert_eq!(
+        solve(&numbers(&[4, 6, 10]), Some(Operation::Lcm), false),
+        Ok(Answer {
+            gcd: None,
+            lcm: Some(60u32.into()),
+            steps: None
+        })
+    );
+    assert!(solve(&[], None, false).is_err());
+    assert_eq!(solve(&numbers(&[3, 0]), None, false), both(3, 0));
+    assert_eq!(solve(&numbers(&[0, 3]), None, false), both(3, 0));
+    assert_eq!(solve(&numbers(&[0, 0]), None, false), both(0, 0));
+
+    // The LCM of these doesn't fit in a u64.
+    let answer = solve(&numbers(&[u64::MAX, u64::MAX - 1]), None, false).unwrap();
+    assert_eq!(answer.gcd, Some(1u32.into()));
+    assert_eq!(answer.lcm, Some(BigUint::from(u64::MAX) * (u64::MAX - 1)));
+
+    // 2^127 - 1 and 2^89 - 1 are Mersenne primes.
+    let p: BigUint = "170141183460469231731687303715884105727".parse().unwrap();
+    let q: BigUint = "618970019642690137449562111".parse().unwrap();
+    let answer = solve(&[&p * &q * 6u32, &q * 15u32], None, false).unwrap();
+    assert_eq!(answer.gcd, Some(&q * 3u32));
+    assert_eq!(answer.lcm, Some(&p * &q * 30u32));
+}
+
+#[test]
+fn test_steps() {
+    let numbers: Vec<BigUint> = [18u32, 12, 4].iter().map(|&n| n.into()).collect();
+    let steps = solve(&numbers, Some(Operation::Gcd), true).unwrap().steps.unwrap();
+    let summary: Vec<(u32, u32, bool, u32)> = steps
+        .iter()
+        .map(|s| (s.n.to_u32().unwrap(), s.m.to_u32().unwrap(), s.swapped, s.remainder.to_u32().unwrap()))
+        .collect();
+    assert_eq!(summary, [(12, 18, true, 6), (6, 12, true, 0), (4, 6, true, 2), (2, 4, true, 0)]);
+
+    assert_eq!(each_step(&numbers, |_| true), Some(2u32.into()));
+    let mut taken = 0;
+    assert_eq!(each_step(&numbers, |_| { taken += 1; taken < 2 }), None);
+    assert_eq!(taken, 2);
+
+    // Steps hold copies of the numbers, so a trace is limited in both.
+    let big = BigUint::from(10u32).pow(MAX_TRACE_DIGITS);
+    assert_eq!(self::steps(&[big.clone() - 1u32, 2u32.into()]).map(|steps| steps.len()), Ok(2));
+    assert_eq!(self::steps(&[big, 2u32.into()]), Err("only numbers of up to 100 digits can be traced"));
+    // Each 1 after the first takes one step.
+    let ones = vec![BigUint::from(1u32); MAX_TRACE_STEPS + 1];
+    assert_eq!(self::steps(&ones).map(|steps| steps.len()), Ok(MAX_TRACE_STEPS));
+    let many = vec![BigUint::from(1u32); MAX_TRACE_STEPS + 2];
+    assert_eq!(solve(&many, None, true), Err("tracing these numbers would take more than 1000 steps"));
+}
+
+#[test]
+fn test_extended_gcd() {
+    for (a, b) in [(240, 46), (46, 240), (14, 15), (7, 0), (u64::MAX, u64::MAX - 1)] {
+        let (g, x, y) = extended_gcd(a, b);
+        assert_eq!(g, if b == 0 { a } else { gcd(a, b) });
+        assert_eq!(a as i128 * x + b as i128 * y, g as i128);
+    }
+    assert_eq!(mod_inverse(3, 11), Some(4));
+    assert_eq!(mod_inverse(3, 12), None);
+    assert_eq!(mod_inverse(5, 0), None);
+}
@@ -0,0 +1,270 @@
+use crate::ratelimit::RateLimiter;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, HttpResponse, web};
+use num::BigUint;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Counters and histograms describing the requests the server has handled,
+/// shared by all the workers and served in Prometheus's text format at
+/// `GET /metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Keyed by route, method and status.
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Keyed by route.
+    durations: BTreeMap<String, Histogram>,
+    /// How many numbers each GCD problem had, and how many digits the
+    /// largest of them had.
+    input_numbers: Option<Histogram>,
+    input_digits: Option<Histogram>,
+}
+
+const DURATION_BUCKETS: &[f64] = &[0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+const NUMBERS_BUCKETS: &[f64] = &[1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0, 100.0, 1000.0];
+const DIGITS_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0];
+
+/// A Prometheus histogram: how many observations fell at or below each of
+/// `bounds`, plus their total.
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(&mut self.counts) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Write the histogram's samples, with `labels` (like `route="/gcd",`)
+    /// added to each.
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, labels, bound, count).unwrap();
+        }
+        writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, labels, self.count).unwrap();
+        let labels = match labels.trim_end_matches(',') {
+            "" => String::new(),
+            labels => format!("{{{}}}", labels),
+        };
+        writeln!(out, "{}_sum{} {}", name, labels, self.sum).unwrap();
+        writeln!(out, "{}_count{} {}", name, labels, self.count).unwrap();
+    }
+}
+
+impl Metrics {
+    pub fn record_request(&self, route: &str, method: &str, status: u16, seconds: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner
+            .requests
+            .entry((route.to_string(), method.to_string(), status))
+            .or_insert(0) += 1;
+        inner
+            .durations
+            .entry(route.to_string())
+            .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+            .observe(seconds);
+    }
+
+    pub fn record_input(&self, numbers: &[BigUint]) {
+        let digits = numbers.iter().map(|n| n.to_string().len()).max().unwrap_or(0);
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .input_numbers
+            .get_or_insert_with(|| Histogram::new(NUMBERS_BUCKETS))
+            .observe(numbers.len() as f64);
+        inner
+            .input_digits
+            .get_or_insert_with(|| Histogram::new(DIGITS_BUCKETS))
+            .observe(digits as f64);
+    }
+
+    /// Everything we've counted, in Prometheus's text exposition format.
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        out += "# HELP gcd_http_requests_total Requests handled, by route, method and status.\n";
+        out += "# TYPE gcd_http_requests_total counter\n";
+        for ((route, method, status), count) in &inner.requests {
+            writeln!(
+                out,
+                "gcd_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                route, method, status, count
+            )
+            .unwrap();
+        }
+
+        out += "# HELP gcd_http_request_duration_seconds How long requests took, by route.\n";
+        out += "# TYPE gcd_http_request_duration_seconds histogram\n";
+        for (route, histogram) in &inner.durations {
+            let labels = format!("route=\"{}\",", route);
+            histogram.write(&mut out, "gcd_http_request_duration_seconds", &labels);
+        }
+
+        let inputs = [
+            ("gcd_input_numbers", "How many numbers each problem had.", &inner.input_numbers),
+            ("gcd_input_digits", "How many digits the largest number in each problem had.", &inner.input_digits),
+        ];
+        for (name, help, histogram) in inputs {
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} histogram", name).unwrap();
+            if let Some(histogram) = histogram {
+                histogram.write(&mut out, name, "");
+            }
+        }
+        out
+    }
+}
+
+/// Count a GCD problem's inputs, if the app has a `Metrics`.
+pub fn record_input(request: &HttpRequest, numbers: &[BigUint]) {
+    if let Some(metrics) = request.app_data::<web::Data<Metrics>>() {
+        metrics.record_input(numbers);
+    }
+}
+
+/// `GET /metrics`
+pub async fn get_metrics(metrics: web::Data<Metrics>, limiter: Option<web::Data<RateLimiter>>) -> HttpResponse {
+    let mut out = metrics.render();
+    if let Some(limiter) = limiter {
+        out += "# HELP gcd_rate_limited_total Requests turned away by the rate limiter.\n";
+        out += "# TYPE gcd_rate_limited_total counter\n";
+        writeln!(out, "gcd_rate_limited_total {}", limiter.status().rejected).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
+
+/// The method of `request`, to label its time series with. Clients can
+/// send any method they like, so only the standard ones get labels of their
+/// own, and the rest share `other`.
+fn method_label(request: &ServiceRequest) -> &'static str {
+    match *request.method() {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "other",
+    }
+}
+
+/// Middleware that counts and times every request, and writes a line of
+/// JSON describing it to standard output.
+pub async fn track<B: MessageBody>(request: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<B>, Error> {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let method_label = method_label(&request);
+    let path = request.path().to_string();
+    let client = request.peer_addr().map(|peer| peer.ip().to_string());
+    let metrics = request.app_data::<web::Data<Metrics>>().cloned();
+
+    let result = next.call(request).await;
+    let seconds = start.elapsed().as_secs_f64();
+
+    // Label requests by the route pattern they matched, not the path, and
+    // by `method_label`, so that a client can't create new time series just
+    // by making up paths or methods.
+    let (route, status) = match &result {
+        Ok(response) => (
+            response.request().match_pattern().unwrap_or_else(|| "unmatched".to_string()),
+            response.status(),
+        ),
+        Err(error) => ("unmatched".to_string(), error.as_response_error().status_code()),
+    };
+    if let Some(metrics) = metrics {
+        metrics.record_request(&route, method_label, status.as_u16(), seconds);
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let line = serde_json::json!({
+        "time": time.as_secs_f64(),
+        "client": client,
+        "method": method,
+        "path": path,
+        "route": route,
+        "status": status.as_u16(),
+        "duration_ms": seconds * 1000.0,
+    });
+    println!("{}", line);
+
+    result
+}
+
+#[test]
+fn test_render() {
+    let metrics = Metrics::default();
+    metrics.record_request("/gcd", "POST", 200, 0.002);
+    metrics.record_request("/gcd", "POST", 200, 0.2);
+    metrics.record_request("/gcd", "POST", 400, 0.001);
+    metrics.record_input(&[BigUint::from(12u32), BigUint::from(180u32)]);
+
+    let text = metrics.render();
+    assert!(text.contains("gcd_http_requests_total{route=\"/gcd\",method=\"POST\",status=\"200\"} 2\n"));
+    assert!(text.contains("gcd_http_requests_total{route=\"/gcd\",method=\"POST\",status=\"400\"} 1\n"));
+    assert!(text.contains("gcd_http_request_duration_seconds_bucket{route=\"/gcd\",le=\"0.005\"} 2\n"));
+    assert!(text.contains("gcd_http_request_duration_seconds_bucket{route=\"/gcd\",le=\"+Inf\"} 3\n"));
+    assert!(text.contains("gcd_http_request_duration_seconds_count{route=\"/gcd\"} 3\n"));
+    assert!(text.contains("gcd_input_numbers_bucket{le=\"2\"} 1\n"));
+    assert!(text.contains("gcd_input_digits_bucket{le=\"1\"} 0\n"));
+    assert!(text.contains("gcd_input_digits_sum 3\n"));
+}
+
+#[actix_web::test]
+async fn test_track() {
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{App, middleware};
+
+    let metrics = web::Data::new(Metrics::default());
+    let app = test::init_service(
+        App::new()
+            .app_data(metrics.clone())
+            .wrap(middleware::from_fn(track))
+            .route("/gcd", web::post().to(HttpResponse::Ok)),
+    )
+    .await;
+    for method in ["POST", "BREW", "X-MADE-UP", "POST"] {
+        let request = TestRequest::default()
+            .method(Method::from_bytes(method.as_bytes()).unwrap())
+            .uri("/gcd");
+        test::call_service(&app, request.to_request()).await;
+    }
+
+    let text = metrics.render();
+    assert!(text.contains("gcd_http_requests_total{route=\"/gcd\",method=\"POST\",status=\"200\"} 2\n"));
+    assert!(text.contains("method=\"other\""), "{}", text);
+    assert!(!text.contains("BREW") && !text.contains("X-MADE-UP"), "{}", text);
+}
@@ -0,0 +1,216 @@
+use actix_web::HttpResponse;
+use serde_json::{Value, json};
+use std::sync::LazyLock;
+
+/// An OpenAPI 3.1 description of the JSON routes for GCD problems, written
+/// out by hand to match the types in `parameters`, `math` and `api`. The
+/// route tests check real responses against it, so the two can't drift apart.
+static DOCUMENT: LazyLock<Value> = LazyLock::new(document);
+
+/// `GET /openapi.json`.
+pub async fn get_openapi() -> HttpResponse {
+    HttpResponse::Ok().json(&*DOCUMENT)
+}
+
+/// A `requestBody` or response `content` holding JSON shaped like `schema`.
+fn json_content(schema: &str) -> Value {
+    json!({ "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } })
+}
+
+/// An operation that takes a JSON body and answers with JSON, or with an
+/// `Error` if the body is malformed or the problem has no answer.
+fn post(summary: &str, request: &str, response: &str) -> Value {
+    json!({
+        "post": {
+            "summary": summary,
+            "requestBody": { "required": true, "content": json_content(request) },
+            "responses": {
+                "200": { "description": "The answer.", "content": json_content(response) },
+                "400": { "description": "The body isn't valid, or there is no answer.", "content": json_content("Error") },
+                "413": { "description": "The body is larger than `--max-request-size`.", "content": json_content("Error") },
+                "429": { "description": "The client has made too many requests; see `Retry-After`." },
+            },
+        }
+    })
+}
+
+fn document() -> Value {
+    let mut gcd = post(
+        "Find the GCD and LCM of a list of numbers",
+        "GcdParameters",
+        "Answer",
+    );
+    gcd["post"]["description"] = json!(
+        "The same as `/api/gcd`, but the body may also be a form, and a client that \
+         prefers `text/html` gets an HTML page."
+    );
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "actix-gcd",
+            "description": "Greatest common divisors and least common multiples of numbers of any size.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/gcd": gcd,
+            "/api/gcd": post("Find the GCD and LCM of a list of numbers", "GcdParameters", "Answer"),
+            "/api/batch": post("Solve several GCD problems at once", "BatchRequest", "BatchResponse"),
+            "/api/bezout": post("Find the GCD of two numbers, with Bézout coefficients", "BezoutRequest", "BezoutResponse"),
+            "/api/inverse": post("Find the inverse of a number modulo another", "InverseRequest", "InverseResponse"),
+        },
+        "components": {
+            "schemas": {
+                "Number": {
+                    "description": "A whole number: a JSON number if it fits in 64 bits, or a string of decimal digits of any length.",
+                    "oneOf": [
+                        { "type": "integer", "minimum": 0 },
+                        { "type": "string", "pattern": "^[0-9]+$" },
+                    ],
+                },
+                "GcdParameters": {
+                    "type": "object",
+                    "required": ["numbers"],
+                    "properties": {
+                        "numbers": {
+                            "description": "An array of numbers, or a string of them separated by commas or spaces.",
+                            "oneOf": [
+                                { "type": "array", "items": { "$ref": "#/components/schemas/Number" }, "minItems": 1 },
+                                { "type": "string", "examples": ["12, 18 30"] },
+                            ],
+                        },
+                        "operation": {
+                            "description": "Find only the GCD or only the LCM, rather than both.",
+                            "type": "string",
+                            "enum": ["gcd", "lcm"],
+                        },
+                        "trace": {
+                            "description": "Whether to list each step Euclid's algorithm took. Only numbers of up to 100 digits can be traced, in at most 1000 steps.",
+                            "type": "boolean",
+                            "default": false,
+                        },
+                    },
+                },
+                "Answer": {
+                    "type": "object",
+                    "properties": {
+                        "gcd": { "$ref": "#/components/schemas/Number" },
+                        "lcm": { "$ref": "#/components/schemas/Number" },
+                        "steps": { "type": "array", "items": { "$ref": "#/components/schemas/Step" } },
+                    },
+                },
+                "Step": {
+                    "type": "object",
+                    "required": ["n", "m", "swapped", "remainder"],
+                    "properties": {
+                        "n": { "$ref": "#/components/schemas/Number" },
+                        "m": { "$ref": "#/components/schemas/Number" },
+                        "swapped": { "type": "boolean" },
+                        "remainder": { "$ref": "#/components/schemas/Number" },
+                    },
+                },
+                "BatchRequest": {
+                    "type": "object",
+                    "required": ["problems"],
+                    "properties": {
+                        "problems": { "type": "array", "items": { "$ref": "#/components/schemas/GcdParameters" } },
+                    },
+                },
+                "BatchResponse": {
+                    "type": "object",
+                    "required": ["results"],
+                    "properties": {
+                        "results": {
+                            "description": "The answer to each problem, or an error in its place.",
+                            "type": "array",
+                            "items": {
+                                "oneOf": [
+                                    { "$ref": "#/components/schemas/Answer" },
+                                    { "$ref": "#/components/schemas/Error" },
+                                ],
+                            },
+                        },
+                    },
+                },
+                "BezoutRequest": {
+                    "type": "object",
+                    "required": ["a", "b"],
+                    "properties": {
+                        "a": { "type": "integer", "minimum": 0 },
+                        "b": { "type": "integer", "minimum": 0 },
+                    },
+                },
+                "BezoutResponse": {
+                    "description": "`a * x + b * y == gcd`.",
+                    "type": "object",
+                    "required": ["gcd", "x", "y"],
+                    "properties": {
+                        "gcd": { "type": "integer", "minimum": 0 },
+                        "x": { "type": "integer" },
+                        "y": { "type": "integer" },
+                    },
+                },
+                "InverseRequest": {
+                    "type": "object",
+                    "required": ["a", "modulus"],
+                    "properties": {
+                        "a": { "type": "integer", "minimum": 0 },
+                        "modulus": { "type": "integer", "minimum": 0 },
+                    },
+                },
+                "InverseResponse": {
+                    "type": "object",
+                    "required": ["inverse"],
+                    "properties": {
+                        "inverse": { "type": "integer", "minimum": 0 },
+                    },
+                },
+                "Error": {
+                    "type": "object",
+                    "required": ["error"],
+                    "properties": {
+                        "error": { "type": "string" },
+                        "fields": {
+                            "description": "For a form, what's wrong with each field.",
+                            "type": "object",
+                            "additionalProperties": { "type": "string" },
+                        },
+                    },
+                },
+            },
+        },
+    })
+}
+
+/// Every `$ref` in `value`.
+#[cfg(test)]
+fn references(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::String(target) if key == "$ref" => found.push(target.clone()),
+                    _ => references(value, found),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| references(value, found)),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_document() {
+    let mut found = Vec::new();
+    references(&DOCUMENT, &mut found);
+    assert!(found.len() > 10);
+    for target in found {
+        let name = target.strip_prefix("#/components/schemas/").unwrap();
+        assert!(DOCUMENT["components"]["schemas"][name].is_object(), "{} is missing", target);
+    }
+
+    // The example in the description should really parse.
+    let example = &DOCUMENT["components"]["schemas"]["GcdParameters"]["properties"]["numbers"]["oneOf"][1]["examples"][0];
+    let body = json!({ "numbers": example });
+    assert!(serde_json::from_value::<actix_gcd::parameters::GcdParameters>(body).is_ok());
+}
@@ -0,0 +1,132 @@
+//! The body of a request to solve a GCD problem, shared by the server and
+//! `gcd-client`, and the rules for reading numbers from forms and JSON.
+
+use crate::math::{self, Operation};
+use num::BigUint;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The numbers to work on, and what to do with them. In a form, `numbers` is
+/// a single field like `"12, 18 30"`; in JSON it's an array like
+/// `[12, "18"]`. The numbers can have as many digits as you like.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GcdParameters {
+    #[serde(deserialize_with = "numbers", serialize_with = "math::serialize_numbers")]
+    pub numbers: Vec<BigUint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<Operation>,
+    /// Whether to show each step of Euclid's algorithm.
+    #[serde(default)]
+    pub trace: bool,
+}
+
+/// Split `text` into numbers at commas and whitespace. If a word isn't a
+/// number, return it as the error.
+pub fn parse_numbers(text: &str) -> Result<Vec<BigUint>, &str> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .map(|word| parse_number(word).ok_or(word))
+        .collect()
+}
+
+pub fn parse_number(text: &str) -> Option<BigUint> {
+    // `BigUint` would also accept a leading `+` and underscores.
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(text.parse().expect("digits should parse"))
+}
+
+/// Deserialize a list of numbers from either an array or a string of
+/// numbers separated by commas or spaces. The numbers may be any size; in an
+/// array, those too big for a JSON number can be written as strings.
+pub fn numbers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<BigUint>, D::Error> {
+    struct NumbersVisitor;
+
+    impl<'de> Visitor<'de> for NumbersVisitor {
+        type Value = Vec<BigUint>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a list of numbers")
+        }
+
+        fn visit_str<E: de::Error>(self, text: &str) -> Result<Vec<BigUint>, E> {
+            parse_numbers(text).map_err(not_a_number)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<BigUint>, A::Error> {
+            let mut numbers = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(Number(n)) = seq.next_element()? {
+                numbers.push(n);
+            }
+            Ok(numbers)
+        }
+    }
+
+    deserializer.deserialize_any(NumbersVisitor)
+}
+
+/// Deserialize a single number, written as `Number` allows.
+pub fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+    Number::deserialize(deserializer).map(|Number(n)| n)
+}
+
+/// Deserialize a number, written as `Number` allows, or `null`.
+pub fn optional_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BigUint>, D::Error> {
+    Ok(Option::<Number>::deserialize(deserializer)?.map(|Number(n)| n))
+}
+
+/// One element of a JSON array of numbers: a number or a string of digits.
+struct Number(BigUint);
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Number, D::Error> {
+        struct NumberVisitor;
+
+        impl Visitor<'_> for NumberVisitor {
+            type Value = Number;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a whole number, or a string of digits")
+            }
+
+            fn visit_u64<E: de::Error>(self, n: u64) -> Result<Number, E> {
+                Ok(Number(n.into()))
+            }
+
+            fn visit_i64<E: de::Error>(self, n: i64) -> Result<Number, E> {
+                Err(E::custom(format!("'{}' is negative", n)))
+            }
+
+            fn visit_str<E: de::Error>(self, text: &str) -> Result<Number, E> {
+                parse_number(text).map(Number).ok_or_else(|| not_a_number(text))
+            }
+        }
+
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+fn not_a_number<E: de::Error>(text: &str) -> E {
+    E::custom(format!("'{}' is not a whole number", text))
+}
+
+#[test]
+fn test_parameters() {
+    let parameters: GcdParameters = serde_json::from_str(r#"{"numbers": [12, "18446744073709551616"]}"#).unwrap();
+    assert_eq!(parameters.numbers, [BigUint::from(12u32), BigUint::from(1u32) << 64]);
+    assert_eq!(parameters.operation, None);
+    assert_eq!(
+        serde_json::to_string(&parameters).unwrap(),
+        r#"{"numbers":[12,"18446744073709551616"],"trace":false}"#
+    );
+
+    let parameters: GcdParameters = serde_json::from_str(r#"{"numbers": "4, 6 8", "operation": "lcm"}"#).unwrap();
+    assert_eq!(parameters.numbers.len(), 3);
+    assert_eq!(parameters.operation, Some(Operation::Lcm));
+
+    for body in [r#"{"numbers": [-1]}"#, r#"{"numbers": ["+1"]}"#, r#"{"numbers": "1 x"}"#] {
+        assert!(serde_json::from_str::<GcdParameters>(body).is_err(), "{}", body);
+    }
+}
@@ -0,0 +1,244 @@
+//! A WebSocket for watching long computations as they go.
+//!
+//! The client sends a problem as a JSON text message, like
+//! `{"type": "gcd", "numbers": ["123456789012345678901234567890", 9876]}` or
+//! `{"type": "factor", "number": 600851475143}`, and gets back a message for
+//! each step of Euclid's algorithm or each prime factor found, then one of
+//! type `done` with the answer. Sending `{"type": "cancel"}` stops the
+//! problem in progress, which ends with a message of type `cancelled`. One
+//! connection works on one problem at a time.
+
+use crate::i18n::{self, Catalog};
+use crate::math::{self, Step, serialize_optional};
+use crate::parameters;
+use crate::theory::{self, PrimePower};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_ws::{Message, MessageStream, Session};
+use askama::Template;
+use num::{BigUint, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::mpsc;
+
+/// A message from the client.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Command {
+    Gcd {
+        #[serde(deserialize_with = "parameters::numbers")]
+        numbers: Vec<BigUint>,
+    },
+    Factor {
+        #[serde(deserialize_with = "parameters::number")]
+        number: BigUint,
+    },
+    Cancel,
+}
+
+/// A problem, checked and ready to work on.
+enum Problem {
+    Gcd(Vec<BigUint>),
+    Factor(u64),
+}
+
+/// A message to the client.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Event {
+    Step(Step),
+    Factor {
+        prime: u64,
+    },
+    Done {
+        #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional")]
+        gcd: Option<BigUint>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        factors: Option<Vec<PrimePower>>,
+    },
+    Cancelled,
+    Error {
+        error: String,
+    },
+}
+
+impl Event {
+    /// Whether this is the last event for its problem.
+    fn is_last(&self) -> bool {
+        matches!(self, Event::Done { .. } | Event::Cancelled | Event::Error { .. })
+    }
+}
+
+/// The problem a connection is working on.
+#[derive(Default)]
+struct Job {
+    /// Set when the client asks us to stop.
+    cancelled: AtomicBool,
+    /// Set once the last event is on its way, so the client may start
+    /// another problem.
+    finished: AtomicBool,
+}
+
+/// The demonstration page.
+#[derive(Template)]
+#[template(path = "progress.html")]
+struct ProgressPage<'a> {
+    t: &'a Catalog,
+}
+
+/// `GET /progress`: a page that shows a computation's progress over the
+/// socket.
+pub async fn get_page(request: HttpRequest) -> HttpResponse {
+    let t = i18n::negotiate(&request);
+    i18n::render(StatusCode::OK, t, &ProgressPage { t })
+}
+
+/// `GET /progress/socket`: open the WebSocket, accepting messages of up to
+/// `max_message_size` bytes.
+pub async fn get_socket(
+    request: HttpRequest,
+    body: web::Payload,
+    max_message_size: usize,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, session, messages) = actix_ws::handle(&request, body)?;
+    actix_web::rt::spawn(converse(session, messages.max_frame_size(max_message_size)));
+    Ok(response)
+}
+
+/// Answer the client's messages until it goes away.
+async fn converse(mut session: Session, mut messages: MessageStream) {
+    let mut job: Option<Arc<Job>> = None;
+    while let Some(Ok(message)) = messages.recv().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Ping(bytes) => {
+                if session.pong(&bytes).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let busy = job.as_ref().is_some_and(|job| !job.finished.load(Ordering::Relaxed));
+        let problem = match serde_json::from_str(&text) {
+            Ok(Command::Cancel) => {
+                if let Some(job) = &job {
+                    job.cancelled.store(true, Ordering::Relaxed);
+                }
+                continue;
+            }
+            Ok(_) if busy => Err("already working on a problem; cancel it first".to_string()),
+            Ok(Command::Gcd { numbers }) if numbers.is_empty() => Err("expected at least one number".to_string()),
+            Ok(Command::Gcd { numbers }) => Ok(Problem::Gcd(numbers)),
+            Ok(Command::Factor { number }) => match number.to_u64() {
+                Some(0) => Err("0 has no prime factorization".to_string()),
+                Some(n) => Ok(Problem::Factor(n)),
+                None => Err(format!("{} is too big to factor; the limit is {}", number, u64::MAX)),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        match problem {
+            Ok(problem) => job = Some(start(problem, session.clone())),
+            Err(error) => {
+                if send(&mut session, &Event::Error { error }).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Don't keep working for a client that isn't listening.
+    if let Some(job) = job {
+        job.cancelled.store(true, Ordering::Relaxed);
+    }
+    let _ = session.close(None).await;
+}
+
+/// Work on `problem` on a thread of its own, sending the events to the
+/// client as they come.
+fn start(problem: Problem, mut session: Session) -> Arc<Job> {
+    // Room for a few events, so the work needn't wait on every message, but
+    // no more, so a slow client slows the work rather than filling memory.
+    let (sender, mut receiver) = mpsc::channel(16);
+    let job = Arc::new(Job::default());
+
+    let worker_job = job.clone();
+    actix_web::rt::task::spawn_blocking(move || work(problem, &worker_job, &sender));
+
+    let sender_job = job.clone();
+    actix_web::rt::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            if event.is_last() {
+                sender_job.finished.store(true, Ordering::Relaxed);
+            }
+            // If the client is gone, dropping `receiver` stops the work.
+            if send(&mut session, &event).await.is_err() {
+                break;
+            }
+        }
+        sender_job.finished.store(true, Ordering::Relaxed);
+    });
+    job
+}
+
+/// Solve `problem`, sending each step to `events`, and finally the answer,
+/// unless `job` is cancelled first.
+fn work(problem: Problem, job: &Job, events: &mpsc::Sender<Event>) {
+    let report = |event| !job.cancelled.load(Ordering::Relaxed) && events.blocking_send(event).is_ok();
+    let last = match problem {
+        Problem::Gcd(numbers) => math::each_step(&numbers, |step| report(Event::Step(step))).map(|gcd| Event::Done {
+            gcd: Some(gcd),
+            factors: None,
+        }),
+        Problem::Factor(n) => {
+            let mut primes = Vec::new();
+            let finished = theory::factor_each(n, |prime| {
+                primes.push(prime);
+                report(Event::Factor { prime })
+            });
+            finished.then(|| Event::Done {
+                gcd: None,
+                factors: Some(theory::collect_powers(primes)),
+            })
+        }
+    };
+    let _ = events.blocking_send(last.unwrap_or(Event::Cancelled));
+}
+
+async fn send(session: &mut Session, event: &Event) -> Result<(), actix_ws::Closed> {
+    let text = serde_json::to_string(event).expect("events should serialize");
+    session.text(text).await
+}
+
+#[test]
+fn test_work() {
+    let run = |problem, cancelled| {
+        let (sender, mut receiver) = mpsc::channel(100);
+        let job = Job::default();
+        job.cancelled.store(cancelled, Ordering::Relaxed);
+        work(problem, &job, &sender);
+        drop(sender);
+        let mut events = Vec::new();
+        while let Some(event) = receiver.blocking_recv() {
+            events.push(serde_json::to_string(&event).unwrap());
+        }
+        events
+    };
+
+    let numbers = [18u32, 12, 4].map(BigUint::from).to_vec();
+    let events = run(Problem::Gcd(numbers.clone()), false);
+    assert_eq!(events.len(), 5);
+    assert_eq!(events[0], r#"{"type":"step","n":12,"m":18,"swapped":true,"remainder":6}"#);
+    assert_eq!(events[4], r#"{"type":"done","gcd":2}"#);
+    assert_eq!(run(Problem::Gcd(numbers), true), [r#"{"type":"cancelled"}"#]);
+
+    let events = run(Problem::Factor(360), false);
+    assert_eq!(events.iter().filter(|event| event.contains(r#""type":"factor""#)).count(), 6);
+    assert_eq!(
+        events.last().unwrap(),
+        r#"{"type":"done","factors":[{"prime":2,"exponent":3},{"prime":3,"exponent":2},{"prime":5,"exponent":1}]}"#
+    );
+}
@@ -0,0 +1,228 @@
+use crate::api::{self, ApiError};
+use crate::capitalize;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A token bucket for each client, shared by all the workers. A client is
+/// an IPv4 address, or an IPv6 /64 network, since anyone with one IPv6
+/// address usually has the whole /64 around it to choose from.
+///
+/// Each client's bucket holds up to `burst` tokens and gains `refill` more
+/// every second. Every request takes a token; a request that finds its
+/// bucket empty is turned away.
+pub struct RateLimiter {
+    burst: f64,
+    refill: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    rejected: AtomicU64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The most clients we track. When a new one would go past this, we forget
+/// the ones whose buckets have filled up again, since they're no different
+/// from new clients, and then, if that's not enough, the ones we've heard
+/// from least recently, until we're down to `PRUNE_TO`. Pruning takes time
+/// in proportion to the number of clients, so the gap between the two means
+/// it happens once per thousand new clients, not on every request.
+const MAX_CLIENTS: usize = 10_000;
+const PRUNE_TO: usize = 9_000;
+
+/// What `GET /status` reports about rate limiting.
+#[derive(Serialize)]
+pub struct RateLimitStatus {
+    pub burst: u32,
+    pub refill_per_second: u32,
+    pub rejected: u64,
+    pub tracked_clients: usize,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, refill: u32) -> RateLimiter {
+        RateLimiter {
+            burst: burst as f64,
+            refill: refill as f64,
+            buckets: Mutex::new(HashMap::new()),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Take a token from `client`'s bucket at time `now`. If there are none,
+    /// return how long until there will be.
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let client = client_key(client);
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(&client) {
+            self.prune(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.tokens_at(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill))
+    }
+
+    fn prune(&self, buckets: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        buckets.retain(|_, bucket| self.tokens_at(bucket, now) < self.burst);
+        if buckets.len() <= PRUNE_TO {
+            return;
+        }
+        let excess = buckets.len() - PRUNE_TO;
+        let mut times: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+        let (_, &mut newest_to_go, _) = times.select_nth_unstable(excess - 1);
+        buckets.retain(|_, bucket| bucket.updated > newest_to_go);
+    }
+
+    /// How many tokens `bucket` holds at time `now`.
+    fn tokens_at(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill).min(self.burst)
+    }
+
+    pub fn status(&self) -> RateLimitStatus {
+        RateLimitStatus {
+            burst: self.burst as u32,
+            refill_per_second: self.refill as u32,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            tracked_clients: self.buckets.lock().unwrap().len(),
+        }
+    }
+}
+
+/// The key of `address`'s bucket: the address itself for IPv4, including
+/// IPv4 addresses mapped into IPv6, and the /64 it's in otherwise.
+fn client_key(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V4(_) => address,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6((u128::from(v6) & !u128::from(u64::MAX)).into()),
+        },
+    }
+}
+
+/// Paths a process supervisor polls, which shouldn't use up the allowance of
+/// the address it polls from, nor be turned away.
+const UNLIMITED: &[&str] = &["/healthz", "/readyz"];
+
+/// Middleware that answers 429 Too Many Requests to clients that have run
+/// out of tokens, if the app has a `RateLimiter`.
+///
+/// Clients are told apart by the address of the connection, not by
+/// `X-Forwarded-For`, which anyone can set.
+pub async fn limit<B: MessageBody>(
+    request: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let limiter = request.app_data::<web::Data<RateLimiter>>();
+    if let (Some(limiter), Some(peer)) = (limiter, request.peer_addr())
+        && !UNLIMITED.contains(&request.path())
+        && let Err(wait) = limiter.check(peer.ip(), Instant::now())
+    {
+        let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+        let message = format!(
+            "too many requests; try again in {} second{}",
+            seconds,
+            if seconds == 1 { "" } else { "s" }
+        );
+        let mut response = HttpResponse::TooManyRequests();
+        response.insert_header((header::RETRY_AFTER, seconds.to_string()));
+        let response = if api::prefers_json(request.request(), false) {
+            response.json(ApiError {
+                error: message,
+                fields: None,
+            })
+        } else {
+            response.content_type("text/html").body(format!("{}.", capitalize(&message)))
+        };
+        return Ok(request.into_response(response).map_into_right_body());
+    }
+    next.call(request).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[test]
+fn test_token_bucket() {
+    let limiter = RateLimiter::new(3, 2);
+    let alice: IpAddr = "192.0.2.1".parse().unwrap();
+    let bob: IpAddr = "192.0.2.2".parse().unwrap();
+    let start = Instant::now();
+
+    // A full bucket allows a burst of three requests.
+    for _ in 0..3 {
+        assert_eq!(limiter.check(alice, start), Ok(()));
+    }
+    assert_eq!(limiter.check(alice, start), Err(Duration::from_millis(500)));
+    // Other clients have their own buckets.
+    assert_eq!(limiter.check(bob, start), Ok(()));
+
+    // Two tokens a second means one every half second.
+    let later = start + Duration::from_millis(600);
+    assert_eq!(limiter.check(alice, later), Ok(()));
+    assert!(limiter.check(alice, later).is_err());
+
+    let status = limiter.status();
+    assert_eq!((status.rejected, status.tracked_clients), (2, 2));
+}
+
+#[test]
+fn test_ipv6_networks() {
+    let limiter = RateLimiter::new(2, 1);
+    let start = Instant::now();
+    let address = |text: &str| text.parse::<IpAddr>().unwrap();
+
+    // Addresses in the same /64 share a bucket...
+    assert_eq!(limiter.check(address("2001:db8:1:2::1"), start), Ok(()));
+    assert_eq!(limiter.check(address("2001:db8:1:2:ffff::9"), start), Ok(()));
+    assert!(limiter.check(address("2001:db8:1:2:abcd:1234:5678:9abc"), start).is_err());
+    // ... but not with the next /64 along.
+    assert_eq!(limiter.check(address("2001:db8:1:3::1"), start), Ok(()));
+
+    // An IPv4 address is the same client however it's written.
+    assert_eq!(limiter.check(address("192.0.2.1"), start), Ok(()));
+    assert_eq!(limiter.check(address("::ffff:192.0.2.1"), start), Ok(()));
+    assert!(limiter.check(address("192.0.2.1"), start).is_err());
+    assert_eq!(limiter.status().tracked_clients, 3);
+}
+
+#[test]
+fn test_max_clients() {
+    let limiter = RateLimiter::new(3, 1);
+    let client = |i: usize| IpAddr::from([0x2001, 0xdb8, (i >> 16) as u16, i as u16, 0, 0, 0, 1]);
+    let start = Instant::now();
+
+    // Lots of clients, a few microseconds apart, so that none of their
+    // buckets has had time to fill up again.
+    let at = |i: usize| start + Duration::from_micros(50 * i as u64);
+    for i in 0..MAX_CLIENTS {
+        assert_eq!(limiter.check(client(i), at(i)), Ok(()));
+    }
+    assert_eq!(limiter.status().tracked_clients, MAX_CLIENTS);
+
+    // One more makes room by forgetting the ones heard from longest ago.
+    assert_eq!(limiter.check(client(MAX_CLIENTS), at(MAX_CLIENTS)), Ok(()));
+    let buckets = limiter.buckets.lock().unwrap();
+    assert_eq!(buckets.len(), PRUNE_TO + 1);
+    assert!(!buckets.contains_key(&client_key(client(0))));
+    assert!(buckets.contains_key(&client_key(client(MAX_CLIENTS - 1))));
+}
@@ -0,0 +1,418 @@
+//! Tests of the routes, run against an `App` set up just as `serve` sets one
+//! up, but without a network in between.
+
+use crate::cache::ResultCache;
+use crate::configure;
+use crate::health::Health;
+use crate::metrics::{self, Metrics};
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use actix_web::{App, middleware, web};
+use serde_json::{Value, json};
+
+/// A small limit, so that it doesn't take much to exceed it.
+const MAX_REQUEST_SIZE: usize = 1024;
+
+/// Send `request` to a fresh app, and return the status, content type and
+/// body of the response.
+async fn call(request: TestRequest) -> (StatusCode, String, String) {
+    let (status, headers, body) = call_with_headers(request).await;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    (status, content_type, body)
+}
+
+/// Like `call`, but return all the response's headers.
+async fn call_with_headers(request: TestRequest) -> (StatusCode, HeaderMap, String) {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Metrics::default()))
+            .app_data(web::Data::new(ResultCache::new(10)))
+            .app_data(web::Data::new(Health::new(1)))
+            .wrap(middleware::from_fn(metrics::track))
+            .configure(|routes| configure(routes, MAX_REQUEST_SIZE)),
+    )
+    .await;
+    let response = test::call_service(&app, request.to_request()).await;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = test::read_body(response).await;
+    (status, headers, String::from_utf8_lossy(&body).into_owned())
+}
+
+fn form(body: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/gcd")
+        .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+        .set_payload(body.to_string())
+}
+
+fn json(uri: &str, body: &str) -> TestRequest {
+    TestRequest::post()
+        .uri(uri)
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .set_payload(body.to_string())
+}
+
+#[actix_web::test]
+async fn test_index() {
+    let (status, content_type, body) = call(TestRequest::get().uri("/")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "text/html");
+    assert!(body.contains(r#"<form action="/gcd" method="post">"#));
+
+    let (status, _, _) = call(TestRequest::get().uri("/nowhere")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_post_form() {
+    let (status, content_type, body) = call(form("numbers=12%2C+18+30")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "text/html");
+    assert!(body.contains("The greatest common divisor of the numbers 12, 18 and 30 is <b>6 </b>"));
+    assert!(body.contains("The least common multiple of the numbers 12, 18 and 30 is <b>180 </b>"));
+
+    let (status, _, body) = call(form("numbers=12+18&operation=lcm&trace=true")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.contains("greatest common divisor"));
+    assert!(body.contains("<table>"));
+
+    // A browser gets the form back, with what's wrong next to each field...
+    let (status, content_type, body) = call(form("numbers=12+x&operation=mod")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(content_type, "text/html");
+    assert!(body.contains(r#"value="12 x""#));
+    assert!(body.contains("&#39;x&#39; is not a whole number."));
+    assert!(body.contains("Choose GCD or LCM."));
+
+    // ... and a client that wants JSON gets the same messages as JSON.
+    let request = form("numbers=").insert_header((header::ACCEPT, "application/json"));
+    let (status, content_type, body) = call(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(content_type, "application/json");
+    assert_eq!(body, r#"{"error":"Enter at least one number.","fields":{"numbers":"Enter at least one number."}}"#);
+}
+
+#[actix_web::test]
+async fn test_language() {
+    let request = |language: &str| {
+        call_with_headers(
+            form("numbers=12+x&operation=gcd").insert_header((header::ACCEPT_LANGUAGE, language.to_string())),
+        )
+    };
+
+    let (status, headers, body) = request("fr-CA, en;q=0.5").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(headers.get(header::CONTENT_LANGUAGE).unwrap(), "fr");
+    assert_eq!(headers.get(header::VARY).unwrap(), "Accept-Language");
+    assert!(body.contains(r#"<html lang="fr">"#));
+    assert!(body.contains("<title>Calculatrice de PGCD</title>"));
+
+    // Languages we have no catalog for get English.
+    let (_, headers, body) = request("de").await;
+    assert_eq!(headers.get(header::CONTENT_LANGUAGE).unwrap(), "en");
+    assert!(body.contains("&#39;x&#39; is not a whole number."));
+}
+
+#[actix_web::test]
+async fn test_post_json() {
+    let (status, content_type, body) = call(json("/gcd", r#"{"numbers": [12, "18"]}"#)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/json");
+    assert_eq!(body, r#"{"gcd":6,"lcm":36}"#);
+
+    let (_, _, body) = call(json("/api/gcd", r#"{"numbers": "12 18", "operation": "gcd"}"#)).await;
+    assert_eq!(body, r#"{"gcd":6}"#);
+
+    // Answers too big for a JSON number come back as strings.
+    let big = r#"{"numbers": ["18446744073709551616", "36893488147419103232"]}"#;
+    let (_, _, body) = call(json("/api/gcd", big)).await;
+    assert_eq!(body, r#"{"gcd":"18446744073709551616","lcm":"36893488147419103232"}"#);
+
+    let request = json("/gcd", r#"{"numbers": [12, 18]}"#).insert_header((header::ACCEPT, "text/html"));
+    let (status, content_type, body) = call(request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "text/html");
+    assert!(body.contains("<b>6 </b>"));
+
+    for (body, message) in [
+        (r#"{"numbers": [-3]}"#, "'-3' is negative"),
+        (r#"{"numbers": ["1.5"]}"#, "'1.5' is not a whole number"),
+        (r#"{"numbers": []}"#, "expected at least one number"),
+        (r#"{"numbers": [1], "operation": "mod"}"#, "unknown variant `mod`"),
+        ("{", "EOF while parsing"),
+    ] {
+        let (status, content_type, response) = call(json("/api/gcd", body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(content_type, "application/json");
+        assert!(response.starts_with(r#"{"error":""#), "{}", response);
+        assert!(response.contains(message), "{} should mention {}", response, message);
+    }
+}
+
+#[actix_web::test]
+async fn test_zero() {
+    let (_, _, body) = call(json("/api/gcd", r#"{"numbers": [0, 5]}"#)).await;
+    assert_eq!(body, r#"{"gcd":5,"lcm":0}"#);
+    let (_, _, body) = call(json("/api/gcd", r#"{"numbers": [0, 0]}"#)).await;
+    assert_eq!(body, r#"{"gcd":0,"lcm":0}"#);
+
+    let (status, _, body) = call(form("numbers=0+0&trace=true")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("The greatest common divisor of the numbers 0 and 0 is <b>0 </b>"));
+}
+
+#[actix_web::test]
+async fn test_oversized() {
+    let numbers = vec!["1"; MAX_REQUEST_SIZE].join(",");
+
+    let (status, content_type, body) = call(json("/api/gcd", &format!(r#"{{"numbers": [{}]}}"#, numbers))).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(content_type, "application/json");
+    assert_eq!(body, r#"{"error":"the request is too large"}"#);
+
+    let request = form(&format!("numbers={}", numbers)).insert_header((header::ACCEPT, "application/json"));
+    let (status, _, body) = call(request).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body, r#"{"error":"the request is too large"}"#);
+
+    let (status, _, _) = call(form(&format!("numbers={}", numbers))).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[actix_web::test]
+async fn test_content_types() {
+    // A body that's neither a form nor JSON.
+    let request = TestRequest::post()
+        .uri("/gcd")
+        .insert_header((header::CONTENT_TYPE, "text/plain"))
+        .insert_header((header::ACCEPT, "application/json"))
+        .set_payload("12 18");
+    let (status, content_type, _) = call(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(content_type, "application/json");
+
+    // The JSON API only takes JSON.
+    let request = TestRequest::post()
+        .uri("/api/gcd")
+        .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+        .set_payload("numbers=12+18");
+    let (status, _, _) = call(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Parameters on the content type don't matter.
+    let request = json("/gcd", r#"{"numbers": [4, 6]}"#)
+        .insert_header((header::CONTENT_TYPE, "application/json; charset=utf-8"));
+    let (status, content_type, _) = call(request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/json");
+
+    for path in ["/healthz", "/readyz", "/version"] {
+        let (status, content_type, _) = call(TestRequest::get().uri(path)).await;
+        assert_eq!((status, content_type.as_str()), (StatusCode::OK, "application/json"), "{}", path);
+    }
+    let (_, _, body) = call(TestRequest::get().uri("/version")).await;
+    assert!(body.contains(&format!(r#""version":"{}""#, env!("CARGO_PKG_VERSION"))));
+
+    let (status, content_type, body) = call(TestRequest::get().uri("/openapi.json")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/json");
+    assert!(body.contains(r#""openapi":"3.1.0""#));
+    assert!(body.contains(r#""/api/gcd":"#));
+
+    let (status, content_type, body) = call(TestRequest::get().uri("/metrics")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "text/plain; version=0.0.4");
+    assert!(body.contains("# TYPE gcd_http_requests_total counter"));
+}
+
+#[actix_web::test]
+async fn test_tools() {
+    let (status, content_type, body) = call(TestRequest::get().uri("/factor")).await;
+    assert_eq!((status, content_type.as_str()), (StatusCode::OK, "text/html"));
+    assert!(body.contains(r#"<form action="/factor" method="post">"#));
+
+    let (_, _, body) = call(json("/factor", r#"{"number": 360}"#)).await;
+    assert_eq!(
+        body,
+        r#"{"number":360,"factors":[{"prime":2,"exponent":3},{"prime":3,"exponent":2},{"prime":5,"exponent":1}]}"#
+    );
+    let request = TestRequest::post()
+        .uri("/factor")
+        .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+        .set_payload("number=360");
+    let (status, _, body) = call(request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("360 = 2<sup>3</sup> &times; 3<sup>2</sup> &times; 5"));
+
+    // Messages from the catalog are escaped like anything else.
+    let tool_form = |path: &str, body: &str| {
+        TestRequest::post()
+            .uri(path)
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .insert_header((header::ACCEPT_LANGUAGE, "fr"))
+            .set_payload(body.to_string())
+    };
+    let (_, _, body) = call(tool_form("/factor", "number=7")).await;
+    assert!(body.contains("<p>7 est premier</p>"));
+    let (_, _, body) = call(tool_form("/factor", "number=1")).await;
+    assert!(body.contains("<p>1 n&#39;a pas de facteurs premiers</p>"));
+    let (_, _, body) = call(tool_form("/shared-factors", "numbers=360+84")).await;
+    assert!(body.contains("<li>84 = 2<sup>2</sup> &times; 3 &times; 7</li>"));
+    assert!(body.contains("360 et 84 ont 2<sup>2</sup> &times; 3 en commun, donc leur plus grand commun diviseur est <b>12</b>"));
+    let (_, _, body) = call(tool_form("/modpow", "base=3&exponent=200&modulus=7")).await;
+    assert!(body.contains("<p>3<sup>200</sup> mod 7 = <b>2</b></p>"));
+    let (_, _, body) = call(tool_form("/crt", "residues=2+3+2&moduli=3+5+7")).await;
+    assert!(body.contains("<p>x &equiv; <b>23</b> (mod 105)</p>"));
+
+    let (_, _, body) = call(json("/shared-factors", r#"{"numbers": [360, 84]}"#)).await;
+    assert!(body.ends_with(r#""shared":[{"prime":2,"exponent":2},{"prime":3,"exponent":1}],"gcd":12}"#));
+
+    let (_, _, body) = call(json("/crt", r#"{"residues": [2, 3, 2], "moduli": "3 5 7"}"#)).await;
+    assert_eq!(body, r#"{"solution":23,"modulus":105}"#);
+
+    let (_, _, body) = call(json("/modpow", r#"{"base": 3, "exponent": 200, "modulus": 7}"#)).await;
+    assert_eq!(body, r#"{"result":2}"#);
+
+    let (status, _, body) = call(json("/inverse", r#"{"a": 3, "modulus": 12}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body, r#"{"error":"3 has no inverse modulo 12"}"#);
+
+    // Form fields are checked one by one, like the GCD form's.
+    let request = TestRequest::post()
+        .uri("/modpow")
+        .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+        .set_payload("base=3&exponent=x&modulus=0");
+    let (status, _, body) = call(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains(r#"value="x""#));
+    assert!(body.contains("&#39;x&#39; is not a whole number."));
+
+    let request = TestRequest::post()
+        .uri("/modpow")
+        .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+        .set_payload("base=3&exponent=2&modulus=0");
+    let (status, _, body) = call(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("The modulus must not be zero."));
+}
+
+#[actix_web::test]
+async fn test_progress() {
+    let (status, content_type, body) = call(TestRequest::get().uri("/progress")).await;
+    assert_eq!((status, content_type.as_str()), (StatusCode::OK, "text/html"));
+    assert!(body.contains(r#"<ol id="events""#));
+
+    let request = TestRequest::get()
+        .uri("/progress/socket")
+        .insert_header((header::CONNECTION, "upgrade"))
+        .insert_header((header::UPGRADE, "websocket"))
+        .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+        .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="));
+    let (status, _, _) = call(request).await;
+    assert_eq!(status, StatusCode::SWITCHING_PROTOCOLS);
+
+    // Without the handshake headers, it's just a bad request.
+    let (status, _, _) = call(TestRequest::get().uri("/progress/socket")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+/// Check `value` against `schema`, looking up `$ref`s in `document`.
+/// Only the parts of JSON Schema that `/openapi.json` uses are understood.
+fn conforms(document: &Value, schema: &Value, value: &Value) -> Result<(), String> {
+    if let Some(target) = schema["$ref"].as_str() {
+        let name = target.trim_start_matches("#/components/schemas/");
+        return conforms(document, &document["components"]["schemas"][name], value)
+            .map_err(|e| format!("{}: {}", name, e));
+    }
+    if let Some(choices) = schema["oneOf"].as_array() {
+        let errors: Vec<String> = choices
+            .iter()
+            .filter_map(|choice| conforms(document, choice, value).err())
+            .collect();
+        return if errors.len() < choices.len() { Ok(()) } else { Err(errors.join("; ")) };
+    }
+    let fits = match schema["type"].as_str() {
+        Some("object") => {
+            let Some(object) = value.as_object() else {
+                return Err(format!("{} is not an object", value));
+            };
+            for key in schema["required"].as_array().into_iter().flatten() {
+                let key = key.as_str().unwrap();
+                if !object.contains_key(key) {
+                    return Err(format!("{} is missing '{}'", value, key));
+                }
+            }
+            for (key, property) in object {
+                let property_schema = match &schema["properties"][key] {
+                    Value::Null if schema["additionalProperties"].is_object() => &schema["additionalProperties"],
+                    Value::Null => return Err(format!("'{}' is not documented", key)),
+                    property_schema => property_schema,
+                };
+                conforms(document, property_schema, property).map_err(|e| format!("{}: {}", key, e))?;
+            }
+            true
+        }
+        Some("array") => {
+            let Some(items) = value.as_array() else {
+                return Err(format!("{} is not an array", value));
+            };
+            for item in items {
+                conforms(document, &schema["items"], item)?;
+            }
+            true
+        }
+        Some("integer") if schema["minimum"] == 0 => value.is_u64(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("string") => value.as_str().is_some_and(|text| {
+            schema["pattern"] != "^[0-9]+$" || text.bytes().all(|byte| byte.is_ascii_digit())
+        }),
+        Some("boolean") => value.is_boolean(),
+        _ => return Err(format!("can't check against {}", schema)),
+    };
+    if fits { Ok(()) } else { Err(format!("{} doesn't fit {}", value, schema)) }
+}
+
+#[actix_web::test]
+async fn test_openapi() {
+    let (status, _, body) = call(TestRequest::get().uri("/openapi.json")).await;
+    assert_eq!(status, StatusCode::OK);
+    let document: Value = serde_json::from_str(&body).unwrap();
+
+    // Each request uses every property its schema documents, and each
+    // response should have only the properties documented for its status.
+    let form_error = form("numbers=").insert_header((header::ACCEPT, "application/json"));
+    let big = r#"{"numbers": ["18446744073709551616", 12], "operation": "gcd", "trace": true}"#;
+    for (path, request) in [
+        ("/gcd", form_error),
+        ("/api/gcd", json("/api/gcd", r#"{"numbers": "12 18", "operation": "lcm", "trace": true}"#)),
+        ("/api/gcd", json("/api/gcd", big)),
+        ("/api/gcd", json("/api/gcd", r#"{"numbers": [-1]}"#)),
+        ("/api/batch", json("/api/batch", r#"{"problems": [{"numbers": [12, 18], "trace": true}, {"numbers": []}]}"#)),
+        ("/api/bezout", json("/api/bezout", r#"{"a": 240, "b": 46}"#)),
+        ("/api/inverse", json("/api/inverse", r#"{"a": 3, "modulus": 11}"#)),
+        ("/api/inverse", json("/api/inverse", r#"{"a": 3, "modulus": 12}"#)),
+    ] {
+        let (status, content_type, body) = call(request).await;
+        assert_eq!(content_type, "application/json", "{} {}", path, body);
+        let response = &document["paths"][path]["post"]["responses"][status.as_str()];
+        let schema = &response["content"]["application/json"]["schema"];
+        assert!(schema.is_object(), "{} doesn't document a {} response", path, status);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        if let Err(e) = conforms(&document, schema, &body) {
+            panic!("{} {}: {}", path, status, e);
+        }
+    }
+
+    // The checker itself should catch a mismatch.
+    let answer = json!({ "$ref": "#/components/schemas/Answer" });
+    assert!(conforms(&document, &answer, &json!({ "gcd": 6, "steps": [] })).is_ok());
+    assert!(conforms(&document, &answer, &json!({ "gcd": -6 })).is_err());
+    assert!(conforms(&document, &answer, &json!({ "gcd": 6, "remainder": 0 })).is_err());
+    assert!(conforms(&document, &answer, &json!({ "steps": [{ "n": 1, "m": 2 }] })).is_err());
+}
@@ -0,0 +1,310 @@
+use crate::math::{self, serialize_number};
+use num::integer::Integer;
+use num::{BigUint, One, Zero};
+use serde::Serialize;
+
+/// A prime and how many times it divides a number.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct PrimePower {
+    pub prime: u64,
+    pub exponent: u32,
+}
+
+/// The solution to a system of congruences: every number congruent to
+/// `solution` modulo `modulus`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Congruence {
+    #[serde(serialize_with = "serialize_number")]
+    pub solution: BigUint,
+    #[serde(serialize_with = "serialize_number")]
+    pub modulus: BigUint,
+}
+
+/// Divide out primes below this by trial division before trying Pollard's
+/// rho, which is slow to find small factors by comparison.
+const TRIAL_DIVISION_LIMIT: u64 = 1000;
+
+/// The prime factors of `n`, smallest first, with their exponents. One has
+/// none; zero has no factorization at all, so it isn't allowed.
+pub fn factor(n: u64) -> Vec<PrimePower> {
+    let mut primes = Vec::new();
+    factor_each(n, |p| {
+        primes.push(p);
+        true
+    });
+    collect_powers(primes)
+}
+
+/// Call `found` with each prime factor of `n` as we find it, repeated as
+/// many times as it divides `n`, and in no particular order. If `found`
+/// returns false, stop looking. Return false if we stopped early.
+pub fn factor_each(mut n: u64, mut found: impl FnMut(u64) -> bool) -> bool {
+    assert!(n != 0, "zero has no prime factorization");
+
+    let mut p = 2;
+    while p < TRIAL_DIVISION_LIMIT && p * p <= n {
+        while n.is_multiple_of(p) {
+            if !found(p) {
+                return false;
+            }
+            n /= p;
+        }
+        p += if p == 2 { 1 } else { 2 };
+    }
+    if n == 1 {
+        return true;
+    }
+
+    // Whatever is left has no factor below the limit. Split it until all
+    // the pieces are prime.
+    let mut pieces = vec![n];
+    while let Some(piece) = pieces.pop() {
+        if is_prime(piece) {
+            if !found(piece) {
+                return false;
+            }
+        } else {
+            let d = pollard_rho(piece);
+            pieces.push(d);
+            pieces.push(piece / d);
+        }
+    }
+    true
+}
+
+/// Sort `primes` and count the repeats.
+pub fn collect_powers(mut primes: Vec<u64>) -> Vec<PrimePower> {
+    primes.sort_unstable();
+    let mut powers: Vec<PrimePower> = Vec::new();
+    for prime in primes {
+        match powers.last_mut() {
+            Some(last) if last.prime == prime => last.exponent += 1,
+            _ => powers.push(PrimePower { prime, exponent: 1 }),
+        }
+    }
+    powers
+}
+
+/// The primes that divide every one of `factorizations`, each to the
+/// highest power that divides them all. Their product is the GCD.
+pub fn shared_factors(factorizations: &[Vec<PrimePower>]) -> Vec<PrimePower> {
+    let Some((first, rest)) = factorizations.split_first() else {
+        return Vec::new();
+    };
+    first
+        .iter()
+        .filter_map(|power| {
+            let mut exponent = power.exponent;
+            for factorization in rest {
+                let other = factorization.iter().find(|other| other.prime == power.prime)?;
+                exponent = exponent.min(other.exponent);
+            }
+            Some(PrimePower {
+                prime: power.prime,
+                exponent,
+            })
+        })
+        .collect()
+}
+
+/// Multiply the prime powers back together.
+pub fn product(powers: &[PrimePower]) -> u64 {
+    powers.iter().map(|power| power.prime.pow(power.exponent)).product()
+}
+
+/// `a * b % m`, without overflowing.
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, m: u64) -> u64 {
+    let mut result = 1 % m;
+    base %= m;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Whether `n` is prime, by the Miller-Rabin test. Testing against the
+/// first twelve primes as witnesses is enough to be sure for every `u64`.
+pub fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for p in WITNESSES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+
+    // Write n - 1 as d * 2^s, with d odd.
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    'witness: for a in WITNESSES {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// Find a factor of `n`, which must be composite and odd, other than 1
+/// and `n`, by Pollard's rho algorithm: iterate `x * x + c` modulo `n`
+/// until two values are congruent modulo some factor of `n`, which shows up
+/// as a common divisor of their difference and `n`.
+fn pollard_rho(n: u64) -> u64 {
+    for c in 1.. {
+        let next = |x: u64| ((x as u128 * x as u128 + c as u128) % n as u128) as u64;
+        let (mut slow, mut fast, mut d) = (2, 2, 1);
+        while d == 1 {
+            slow = next(slow);
+            fast = next(next(fast));
+            d = math::gcd(slow.abs_diff(fast), n);
+        }
+        // If the two met modulo `n` itself, try another `c`.
+        if d != n {
+            return d;
+        }
+    }
+    unreachable!()
+}
+
+/// Solve the system of congruences `x ≡ residues[i] (mod moduli[i])` by
+/// the Chinese Remainder Theorem. The moduli needn't be coprime, but then
+/// there might not be a solution.
+pub fn crt(residues: &[BigUint], moduli: &[BigUint]) -> Result<Congruence, String> {
+    if residues.len() != moduli.len() {
+        return Err("expected as many residues as moduli".to_string());
+    }
+    if residues.is_empty() {
+        return Err("expected at least one congruence".to_string());
+    }
+    if moduli.iter().any(Zero::is_zero) {
+        return Err("the moduli must not be zero".to_string());
+    }
+
+    let mut solution = BigUint::zero();
+    let mut modulus = BigUint::one();
+    for (residue, m) in residues.iter().zip(moduli) {
+        // We want x with x ≡ solution (mod modulus) and x ≡ residue (mod m).
+        // Writing x = solution + modulus * k, that means
+        // modulus * k ≡ residue - solution (mod m).
+        let g = modulus.gcd(m);
+        let difference = (residue % m + m - &solution % m) % m;
+        if !(&difference % &g).is_zero() {
+            return Err(format!(
+                "there is no solution: x can't be both {} modulo {} and {} modulo {}",
+                solution, modulus, residue, m
+            ));
+        }
+        let m_over_g = m / &g;
+        let k = match (&modulus / &g).modinv(&m_over_g) {
+            Some(inverse) => difference / &g * inverse % &m_over_g,
+            None => unreachable!("modulus / g and m / g are coprime"),
+        };
+        solution += &modulus * k;
+        modulus *= m_over_g;
+        solution %= &modulus;
+    }
+    Ok(Congruence { solution, modulus })
+}
+
+/// The most decimal digits the exponent and modulus given to `mod_pow` may
+/// have. The time it takes grows with the cube of their size, so without a
+/// limit, one request could keep a thread busy for minutes.
+pub const MAX_MOD_POW_DIGITS: u32 = 1000;
+
+/// `base` to the power `exponent`, modulo `modulus`.
+pub fn mod_pow(base: &BigUint, exponent: &BigUint, modulus: &BigUint) -> Result<BigUint, String> {
+    if modulus.is_zero() {
+        return Err("the modulus must not be zero".to_string());
+    }
+    let limit = BigUint::from(10u32).pow(MAX_MOD_POW_DIGITS);
+    if *exponent >= limit || *modulus >= limit {
+        return Err(format!(
+            "the exponent and modulus can have at most {} digits",
+            MAX_MOD_POW_DIGITS
+        ));
+    }
+    Ok(base.modpow(exponent, modulus))
+}
+
+/// The inverse of `a` modulo `modulus`, for numbers of any size.
+pub fn mod_inverse(a: &BigUint, modulus: &BigUint) -> Result<BigUint, String> {
+    if modulus.is_zero() {
+        return Err("the modulus must not be zero".to_string());
+    }
+    a.modinv(modulus)
+        .ok_or_else(|| format!("{} has no inverse modulo {}", a, modulus))
+}
+
+#[test]
+fn test_factor() {
+    let power = |prime, exponent| PrimePower { prime, exponent };
+    assert_eq!(factor(1), []);
+    assert_eq!(factor(360), [power(2, 3), power(3, 2), power(5, 1)]);
+    assert_eq!(factor(u64::MAX - 58), [power(u64::MAX - 58, 1)]);
+    // Two primes just below 2^32, and the square of one, which trial
+    // division would take far too long to split.
+    assert_eq!(factor(4294967291 * 4294967279), [power(4294967279, 1), power(4294967291, 1)]);
+    assert_eq!(factor(4294967291 * 4294967291), [power(4294967291, 2)]);
+    assert_eq!(factor(1009 * 1009 * 1013 * 2), [power(2, 1), power(1009, 2), power(1013, 1)]);
+    for n in 1..2000 {
+        assert_eq!(product(&factor(n)), n);
+    }
+    for n in [1, 4, 561, 1_000_000_007 * 998_244_353] {
+        assert!(!is_prime(n) || n == 1_000_000_007, "{}", n);
+    }
+    assert!(is_prime(1_000_000_007));
+
+    let mut count = 0;
+    assert!(!factor_each(1 << 20, |_| {
+        count += 1;
+        count < 3
+    }));
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn test_shared_factors() {
+    let shared = shared_factors(&[factor(360), factor(84), factor(1260)]);
+    assert_eq!(product(&shared), 12);
+    assert_eq!(shared, [PrimePower { prime: 2, exponent: 2 }, PrimePower { prime: 3, exponent: 1 }]);
+    assert_eq!(shared_factors(&[factor(14), factor(15)]), []);
+}
+
+#[test]
+fn test_crt() {
+    let big = |list: &[u64]| list.iter().map(|&n| BigUint::from(n)).collect::<Vec<_>>();
+    let answer = crt(&big(&[2, 3, 2]), &big(&[3, 5, 7])).unwrap();
+    assert_eq!((answer.solution, answer.modulus), (23u32.into(), 105u32.into()));
+    // Moduli with a common factor, but consistent residues.
+    let answer = crt(&big(&[3, 5]), &big(&[4, 6])).unwrap();
+    assert_eq!((answer.solution, answer.modulus), (11u32.into(), 12u32.into()));
+    assert!(crt(&big(&[1, 2]), &big(&[4, 6])).unwrap_err().contains("no solution"));
+    assert!(crt(&big(&[1]), &big(&[0])).is_err());
+    assert!(crt(&big(&[1, 2]), &big(&[3])).is_err());
+
+    let m = BigUint::from(u64::MAX - 58);
+    assert_eq!(mod_pow(&2u32.into(), &(&m - 1u32), &m), Ok(BigUint::one()));
+    let limit = BigUint::from(10u32).pow(MAX_MOD_POW_DIGITS);
+    assert!(mod_pow(&2u32.into(), &(&limit - 1u32), &(&limit - 1u32)).is_ok());
+    assert!(mod_pow(&2u32.into(), &limit, &m).unwrap_err().contains("at most 1000 digits"));
+    assert!(mod_pow(&2u32.into(), &m, &limit).is_err());
+    assert_eq!(mod_inverse(&3u32.into(), &11u32.into()), Ok(4u32.into()));
+    assert!(mod_inverse(&3u32.into(), &12u32.into()).is_err());
+}
@@ -0,0 +1,49 @@
+use rustls::ServerConfig;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+
+/// Build a TLS configuration from a PEM file holding the server's
+/// certificate chain, leaf first, and another holding its private key.
+pub fn load(cert_file: &str, key_file: &str) -> io::Result<ServerConfig> {
+    let in_file = |file: &str, e: io::Error| io::Error::new(e.kind(), format!("{}: {}", file, e));
+
+    let mut certs = BufReader::new(File::open(cert_file).map_err(|e| in_file(cert_file, e))?);
+    let chain = rustls_pemfile::certs(&mut certs)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| in_file(cert_file, e))?;
+    if chain.is_empty() {
+        return Err(in_file(cert_file, io::Error::other("no certificates found")));
+    }
+
+    let mut keys = BufReader::new(File::open(key_file).map_err(|e| in_file(key_file, e))?);
+    let key = rustls_pemfile::private_key(&mut keys)
+        .map_err(|e| in_file(key_file, e))?
+        .ok_or_else(|| in_file(key_file, io::Error::other("no private key found")))?;
+
+    // Name the crypto provider rather than leaving rustls to pick one from its
+    // features: `gcd-client`'s HTTP library turns on `ring` as well as the
+    // default `aws-lc-rs`, and with both, rustls won't choose.
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_load() {
+    // A self-signed certificate for `localhost`, made for this test with
+    //     openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes \
+    //         -keyout localhost-key.pem -out localhost.pem -days 36500 -subj /CN=localhost \
+    //         -addext subjectAltName=DNS:localhost
+    let cert = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/localhost.pem");
+    let key = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/localhost-key.pem");
+    assert!(load(cert, key).is_ok());
+
+    // The files the wrong way round.
+    let error = load(key, cert).unwrap_err();
+    assert!(error.to_string().contains("no certificates found"), "{}", error);
+    assert!(load(cert, "no-such-key.pem").is_err());
+}
@@ -0,0 +1,564 @@
+//! Number-theory tools beyond the GCD: factorization, the Chinese Remainder
+//! Theorem, and modular arithmetic.
+//!
+//! Each tool lives at a path like `/factor`, where `GET` shows its form,
+//! and `POST` answers with HTML for a form body, or JSON for a JSON body,
+//! just like `/gcd`.
+
+use crate::api;
+use crate::i18n::{self, Catalog};
+use crate::math::serialize_number;
+use crate::parameters::{self, parse_number, parse_numbers};
+use crate::theory::{self, Congruence, PrimePower};
+use crate::{capitalize, list};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, web};
+use askama::Template;
+use num::{BigUint, ToPrimitive};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// The fields of a submitted form, by name.
+type Fields = HashMap<String, String>;
+
+/// What's wrong with each field of a form.
+type Errors = BTreeMap<&'static str, String>;
+
+/// One text field of a tool's form. Its label is the message with the
+/// same name in the tool's table of the catalog.
+pub struct Field {
+    name: &'static str,
+    placeholder: &'static str,
+}
+
+trait Tool: 'static {
+    const PATH: &'static str;
+    /// The table of the tool's messages in the catalogs, which has its
+    /// title and the labels of its fields.
+    const NAME: &'static str;
+    const FIELDS: &'static [Field];
+
+    /// The body of a JSON request.
+    type Request: DeserializeOwned + Send;
+    /// The body of a JSON response.
+    type Response: Serialize + Send;
+
+    /// Read a request from a submitted form, or say what's wrong with its
+    /// fields.
+    fn parse(form: &Fields, t: &Catalog) -> Result<Self::Request, Errors>;
+
+    fn solve(request: &Self::Request) -> Result<Self::Response, String>;
+
+    /// The answer, to show below the form.
+    fn answer<'a>(t: &Catalog, request: &'a Self::Request, response: &'a Self::Response) -> ToolAnswer<'a>;
+}
+
+/// Add each tool's routes to an `App`.
+pub fn configure(routes: &mut web::ServiceConfig) {
+    register::<Factor>(routes);
+    register::<SharedFactors>(routes);
+    register::<Crt>(routes);
+    register::<ModPow>(routes);
+    register::<ModInverse>(routes);
+}
+
+fn register<T: Tool>(routes: &mut web::ServiceConfig) {
+    routes.service(
+        web::resource(T::PATH)
+            .route(web::get().to(get_form::<T>))
+            .route(web::post().guard(api::json_body()).to(post_json::<T>))
+            .route(web::post().to(post_form::<T>)),
+    );
+}
+
+async fn get_form<T: Tool>(request: HttpRequest) -> HttpResponse {
+    let t = i18n::negotiate(&request);
+    page::<T>(StatusCode::OK, t, &Fields::new(), &Errors::new(), None, None)
+}
+
+/// Solve `request` on the thread pool for blocking work, since some
+/// problems take long enough that they would hold up every other request on
+/// the same worker. Hand `request` back along with the answer.
+async fn solve<T: Tool>(
+    request: T::Request,
+) -> Result<(T::Request, Result<T::Response, String>), actix_web::error::BlockingError> {
+    web::block(move || {
+        let response = T::solve(&request);
+        (request, response)
+    })
+    .await
+}
+
+async fn post_form<T: Tool>(form: web::Form<Fields>, request: HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+    let json = api::prefers_json(&request, false);
+    let t = i18n::negotiate(&request);
+    let parsed = match T::parse(&form, t) {
+        Ok(parsed) => parsed,
+        Err(errors) if json => {
+            let messages: Vec<&str> = errors.values().map(String::as_str).collect();
+            return Ok(api::bad_request(&messages.join(" ")));
+        }
+        Err(errors) => return Ok(page::<T>(StatusCode::BAD_REQUEST, t, &form, &errors, None, None)),
+    };
+    let (parsed, solved) = solve::<T>(parsed).await?;
+    Ok(match solved {
+        Ok(response) if json => HttpResponse::Ok().json(response),
+        Ok(response) => {
+            let answer = T::answer(t, &parsed, &response);
+            page::<T>(StatusCode::OK, t, &form, &Errors::new(), None, Some(answer))
+        }
+        Err(message) if json => api::bad_request(&message),
+        Err(message) => {
+            let error = format!("{}.", capitalize(&message));
+            page::<T>(StatusCode::BAD_REQUEST, t, &form, &Errors::new(), Some(error), None)
+        }
+    })
+}
+
+async fn post_json<T: Tool>(request: web::Json<T::Request>) -> Result<HttpResponse, actix_web::Error> {
+    let (_, solved) = solve::<T>(request.into_inner()).await?;
+    Ok(match solved {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(message) => api::bad_request(&message),
+    })
+}
+
+/// A tool's page: its form, and the answer or what went wrong.
+#[derive(Template)]
+#[template(path = "tool.html")]
+struct ToolPage<'a> {
+    t: &'a Catalog,
+    title: String,
+    path: &'static str,
+    fields: Vec<FieldView<'a>>,
+    error: Option<String>,
+    answer: Option<ToolAnswer<'a>>,
+}
+
+/// What a tool found, in the shape `tool.html` shows it.
+enum ToolAnswer<'a> {
+    Factorization(FactorizationView<'a>),
+    /// `before`, then the shared factors, then `after`, which together make
+    /// up the catalog's sentence, and then the GCD.
+    SharedFactors {
+        numbers: Vec<FactorizationView<'a>>,
+        before: String,
+        shared: &'a [PrimePower],
+        after: String,
+        gcd: u64,
+    },
+    Congruence(&'a Congruence),
+    ModPow {
+        base: &'a BigUint,
+        exponent: &'a BigUint,
+        modulus: &'a BigUint,
+        result: &'a BigUint,
+    },
+    ModInverse {
+        a: &'a BigUint,
+        modulus: &'a BigUint,
+        result: &'a BigUint,
+    },
+}
+
+/// A number's prime factorization, or a sentence saying it hasn't much of
+/// one.
+enum FactorizationView<'a> {
+    Sentence(String),
+    Powers { number: u64, powers: &'a [PrimePower] },
+}
+
+/// A `Field`, filled in.
+struct FieldView<'a> {
+    name: &'static str,
+    label: String,
+    value: &'a str,
+    placeholder: &'static str,
+    error: Option<&'a str>,
+}
+
+/// The tool's form, filled in with `form` and showing `errors`, followed by
+/// `error` or `answer`.
+fn page<T: Tool>(
+    status: StatusCode,
+    t: &Catalog,
+    form: &Fields,
+    errors: &Errors,
+    error: Option<String>,
+    answer: Option<ToolAnswer>,
+) -> HttpResponse {
+    let fields = T::FIELDS
+        .iter()
+        .map(|field| FieldView {
+            name: field.name,
+            label: format!("{}.{}", T::NAME, field.name),
+            value: form.get(field.name).map_or("", String::as_str),
+            placeholder: field.placeholder,
+            error: errors.get(field.name).map(String::as_str),
+        })
+        .collect();
+    let page = ToolPage {
+        t,
+        title: format!("{}.title", T::NAME),
+        path: T::PATH,
+        fields,
+        error,
+        answer,
+    };
+    i18n::render(status, t, &page)
+}
+
+/// Reads the fields of a form, keeping track of what's wrong with them.
+struct FormReader<'a> {
+    form: &'a Fields,
+    t: &'a Catalog,
+    errors: Errors,
+}
+
+impl<'a> FormReader<'a> {
+    fn new(form: &'a Fields, t: &'a Catalog) -> FormReader<'a> {
+        FormReader {
+            form,
+            t,
+            errors: Errors::new(),
+        }
+    }
+
+    fn text(&self, name: &str) -> &'a str {
+        self.form.get(name).map(|text| text.trim()).unwrap_or("")
+    }
+
+    /// The number in the field `name`, or zero if there isn't one.
+    fn number(&mut self, name: &'static str) -> BigUint {
+        let text = self.text(name);
+        if text.is_empty() {
+            self.errors.insert(name, self.t.get("error.no-number").to_string());
+        } else if let Some(n) = parse_number(text) {
+            return n;
+        } else {
+            self.errors.insert(name, self.t.format("error.not-a-number", &[text]));
+        }
+        BigUint::default()
+    }
+
+    /// The numbers in the field `name`, separated by commas or spaces.
+    fn numbers(&mut self, name: &'static str) -> Vec<BigUint> {
+        match parse_numbers(self.text(name)) {
+            Ok(numbers) if numbers.is_empty() => {
+                self.errors.insert(name, self.t.get("error.no-numbers").to_string());
+                numbers
+            }
+            Ok(numbers) => numbers,
+            Err(word) => {
+                self.errors.insert(name, self.t.format("error.not-a-number", &[word]));
+                Vec::new()
+            }
+        }
+    }
+
+    fn finish<T>(self, request: T) -> Result<T, Errors> {
+        if self.errors.is_empty() { Ok(request) } else { Err(self.errors) }
+    }
+}
+
+/// The prime factors of a number.
+#[derive(Serialize)]
+pub struct Factorization {
+    number: u64,
+    factors: Vec<PrimePower>,
+}
+
+/// Factor `n`, if it's small enough and isn't zero.
+fn factorization(n: &BigUint) -> Result<Factorization, String> {
+    match n.to_u64() {
+        Some(0) => Err("0 has no prime factorization".to_string()),
+        Some(number) => Ok(Factorization {
+            number,
+            factors: theory::factor(number),
+        }),
+        None => Err(format!("{} is too big to factor; the limit is {}", n, u64::MAX)),
+    }
+}
+
+fn factorization_view<'a>(t: &Catalog, factorization: &'a Factorization) -> FactorizationView<'a> {
+    let number = factorization.number.to_string();
+    match factorization.factors.as_slice() {
+        [] => FactorizationView::Sentence(t.format("factor.none", &[&number])),
+        [PrimePower { exponent: 1, .. }] => FactorizationView::Sentence(t.format("factor.prime", &[&number])),
+        factors => FactorizationView::Powers {
+            number: factorization.number,
+            powers: factors,
+        },
+    }
+}
+
+/// `POST /factor`: `{"number": 360}`
+struct Factor;
+
+#[derive(Deserialize)]
+pub struct FactorRequest {
+    #[serde(deserialize_with = "parameters::number")]
+    number: BigUint,
+}
+
+impl Tool for Factor {
+    const PATH: &'static str = "/factor";
+    const NAME: &'static str = "factor";
+    const FIELDS: &'static [Field] = &[Field {
+        name: "number",
+        placeholder: "360",
+    }];
+
+    type Request = FactorRequest;
+    type Response = Factorization;
+
+    fn parse(form: &Fields, t: &Catalog) -> Result<FactorRequest, Errors> {
+        let mut reader = FormReader::new(form, t);
+        let number = reader.number("number");
+        reader.finish(FactorRequest { number })
+    }
+
+    fn solve(request: &FactorRequest) -> Result<Factorization, String> {
+        factorization(&request.number)
+    }
+
+    fn answer<'a>(t: &Catalog, _request: &'a FactorRequest, response: &'a Factorization) -> ToolAnswer<'a> {
+        ToolAnswer::Factorization(factorization_view(t, response))
+    }
+}
+
+/// `POST /shared-factors`: `{"numbers": [360, 84]}`. Find the GCD by
+/// factoring each number and keeping the primes they have in common.
+struct SharedFactors;
+
+#[derive(Deserialize)]
+pub struct SharedFactorsRequest {
+    #[serde(deserialize_with = "parameters::numbers")]
+    numbers: Vec<BigUint>,
+}
+
+#[derive(Serialize)]
+pub struct SharedFactorsResponse {
+    numbers: Vec<Factorization>,
+    shared: Vec<PrimePower>,
+    gcd: u64,
+}
+
+impl Tool for SharedFactors {
+    const PATH: &'static str = "/shared-factors";
+    const NAME: &'static str = "shared-factors";
+    const FIELDS: &'static [Field] = &[Field {
+        name: "numbers",
+        placeholder: "360, 84",
+    }];
+
+    type Request = SharedFactorsRequest;
+    type Response = SharedFactorsResponse;
+
+    fn parse(form: &Fields, t: &Catalog) -> Result<SharedFactorsRequest, Errors> {
+        let mut reader = FormReader::new(form, t);
+        let numbers = reader.numbers("numbers");
+        reader.finish(SharedFactorsRequest { numbers })
+    }
+
+    fn solve(request: &SharedFactorsRequest) -> Result<SharedFactorsResponse, String> {
+        if request.numbers.is_empty() {
+            return Err("expected at least one number".to_string());
+        }
+        let numbers = request
+            .numbers
+            .iter()
+            .map(factorization)
+            .collect::<Result<Vec<_>, _>>()?;
+        let factors: Vec<Vec<PrimePower>> = numbers.iter().map(|n| n.factors.clone()).collect();
+        let shared = theory::shared_factors(&factors);
+        Ok(SharedFactorsResponse {
+            gcd: theory::product(&shared),
+            numbers,
+            shared,
+        })
+    }
+
+    fn answer<'a>(
+        t: &Catalog,
+        request: &'a SharedFactorsRequest,
+        response: &'a SharedFactorsResponse,
+    ) -> ToolAnswer<'a> {
+        let numbers = list(&request.numbers, t);
+        let sentence = if response.shared.is_empty() {
+            t.format("shared-factors.none", &[&numbers])
+        } else {
+            // The factors go in the middle of the sentence, as markup of
+            // their own.
+            t.format("shared-factors.common", &[&numbers, "{1}"])
+        };
+        let (before, after) = sentence.split_once("{1}").unwrap_or((&sentence, ""));
+        ToolAnswer::SharedFactors {
+            numbers: response.numbers.iter().map(|n| factorization_view(t, n)).collect(),
+            before: before.to_string(),
+            shared: &response.shared,
+            after: after.to_string(),
+            gcd: response.gcd,
+        }
+    }
+}
+
+/// `POST /crt`: `{"residues": [2, 3, 2], "moduli": [3, 5, 7]}`. Find `x`
+/// such that `x` is 2 modulo 3, 3 modulo 5, and 2 modulo 7.
+struct Crt;
+
+#[derive(Deserialize)]
+pub struct CrtRequest {
+    #[serde(deserialize_with = "parameters::numbers")]
+    residues: Vec<BigUint>,
+    #[serde(deserialize_with = "parameters::numbers")]
+    moduli: Vec<BigUint>,
+}
+
+impl Tool for Crt {
+    const PATH: &'static str = "/crt";
+    const NAME: &'static str = "crt";
+    const FIELDS: &'static [Field] = &[
+        Field {
+            name: "residues",
+            placeholder: "2, 3, 2",
+        },
+        Field {
+            name: "moduli",
+            placeholder: "3, 5, 7",
+        },
+    ];
+
+    type Request = CrtRequest;
+    type Response = Congruence;
+
+    fn parse(form: &Fields, t: &Catalog) -> Result<CrtRequest, Errors> {
+        let mut reader = FormReader::new(form, t);
+        let residues = reader.numbers("residues");
+        let moduli = reader.numbers("moduli");
+        reader.finish(CrtRequest { residues, moduli })
+    }
+
+    fn solve(request: &CrtRequest) -> Result<Congruence, String> {
+        theory::crt(&request.residues, &request.moduli)
+    }
+
+    fn answer<'a>(_t: &Catalog, _request: &'a CrtRequest, response: &'a Congruence) -> ToolAnswer<'a> {
+        ToolAnswer::Congruence(response)
+    }
+}
+
+/// The body of a response that's a single number.
+#[derive(Serialize)]
+pub struct NumberResponse {
+    #[serde(serialize_with = "serialize_number")]
+    result: BigUint,
+}
+
+/// `POST /modpow`: `{"base": 3, "exponent": 200, "modulus": 7}`
+struct ModPow;
+
+#[derive(Deserialize)]
+pub struct ModPowRequest {
+    #[serde(deserialize_with = "parameters::number")]
+    base: BigUint,
+    #[serde(deserialize_with = "parameters::number")]
+    exponent: BigUint,
+    #[serde(deserialize_with = "parameters::number")]
+    modulus: BigUint,
+}
+
+impl Tool for ModPow {
+    const PATH: &'static str = "/modpow";
+    const NAME: &'static str = "modpow";
+    const FIELDS: &'static [Field] = &[
+        Field {
+            name: "base",
+            placeholder: "3",
+        },
+        Field {
+            name: "exponent",
+            placeholder: "200",
+        },
+        Field {
+            name: "modulus",
+            placeholder: "7",
+        },
+    ];
+
+    type Request = ModPowRequest;
+    type Response = NumberResponse;
+
+    fn parse(form: &Fields, t: &Catalog) -> Result<ModPowRequest, Errors> {
+        let mut reader = FormReader::new(form, t);
+        let base = reader.number("base");
+        let exponent = reader.number("exponent");
+        let modulus = reader.number("modulus");
+        reader.finish(ModPowRequest { base, exponent, modulus })
+    }
+
+    fn solve(request: &ModPowRequest) -> Result<NumberResponse, String> {
+        let result = theory::mod_pow(&request.base, &request.exponent, &request.modulus)?;
+        Ok(NumberResponse { result })
+    }
+
+    fn answer<'a>(_t: &Catalog, request: &'a ModPowRequest, response: &'a NumberResponse) -> ToolAnswer<'a> {
+        ToolAnswer::ModPow {
+            base: &request.base,
+            exponent: &request.exponent,
+            modulus: &request.modulus,
+            result: &response.result,
+        }
+    }
+}
+
+/// `POST /inverse`: `{"a": 3, "modulus": 11}`. Unlike `/api/inverse`, the
+/// numbers can be any size.
+struct ModInverse;
+
+#[derive(Deserialize)]
+pub struct ModInverseRequest {
+    #[serde(deserialize_with = "parameters::number")]
+    a: BigUint,
+    #[serde(deserialize_with = "parameters::number")]
+    modulus: BigUint,
+}
+
+impl Tool for ModInverse {
+    const PATH: &'static str = "/inverse";
+    const NAME: &'static str = "inverse";
+    const FIELDS: &'static [Field] = &[
+        Field {
+            name: "a",
+            placeholder: "3",
+        },
+        Field {
+            name: "modulus",
+            placeholder: "11",
+        },
+    ];
+
+    type Request = ModInverseRequest;
+    type Response = NumberResponse;
+
+    fn parse(form: &Fields, t: &Catalog) -> Result<ModInverseRequest, Errors> {
+        let mut reader = FormReader::new(form, t);
+        let a = reader.number("a");
+        let modulus = reader.number("modulus");
+        reader.finish(ModInverseRequest { a, modulus })
+    }
+
+    fn solve(request: &ModInverseRequest) -> Result<NumberResponse, String> {
+        let result = theory::mod_inverse(&request.a, &request.modulus)?;
+        Ok(NumberResponse { result })
+    }
+
+    fn answer<'a>(_t: &Catalog, request: &'a ModInverseRequest, response: &'a NumberResponse) -> ToolAnswer<'a> {
+        ToolAnswer::ModInverse {
+            a: &request.a,
+            modulus: &request.modulus,
+            result: &response.result,
+        }
+    }
+}
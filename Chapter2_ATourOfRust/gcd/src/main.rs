@@ -1,3 +1,4 @@
+use num::{BigUint, ToPrimitive, Zero};
 use std::str::FromStr;
 use std::env;
 
@@ -5,31 +6,141 @@ fn gcd(mut n: u64, mut m: u64) -> u64 {
     assert!(m != 0 && n != 0);
     while m != 0 {
         if m < n {
-            let t = m;
-            m = n;
-            n = t;
+            std::mem::swap(&mut m, &mut n);
         }
-        m = m % n;
+        m %= n;
     }
     n
 }
 
+/// The same algorithm as `gcd`, for numbers of any size. Once both numbers
+/// fit in a `u64`, it finishes the job with `gcd`, which is much faster.
+fn big_gcd(mut n: BigUint, mut m: BigUint) -> BigUint {
+    assert!(!m.is_zero() && !n.is_zero());
+    while !m.is_zero() {
+        if let (Some(a), Some(b)) = (n.to_u64(), m.to_u64()) {
+            return gcd(a, b).into();
+        }
+        if m < n {
+            std::mem::swap(&mut m, &mut n);
+        }
+        m %= &n;
+    }
+    n
+}
+
+/// Return `(g, x, y)` such that `g` is the greatest common divisor of `a` and
+/// `b`, and `a * x + b * y == g`.
+///
+/// The coefficients are never larger in magnitude than `a` or `b`, so they
+/// always fit in an `i128`, as do the products in the loop.
+fn extended_gcd(a: u64, b: u64) -> (u64, i128, i128) {
+    let (mut old_r, mut r) = (a as i128, b as i128);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_x, x) = (x, old_x - q * x);
+        (old_y, y) = (y, old_y - q * y);
+    }
+    (old_r as u64, old_x, old_y)
+}
+
+/// The number `x` from 0 to `modulus - 1` such that `a * x` is 1 modulo
+/// `modulus`, if there is one. There is exactly when `a` and `modulus` are
+/// coprime.
+fn mod_inverse(a: u64, modulus: u64) -> Option<u64> {
+    if modulus == 0 {
+        return None;
+    }
+    let (g, x, _) = extended_gcd(a % modulus, modulus);
+    if g != 1 {
+        return None;
+    }
+    Some(x.rem_euclid(modulus as i128) as u64)
+}
+
+const USAGE: &str = "Usage: gcd NUMBER ...
+       gcd --bezout A B
+       gcd --inverse A MODULUS";
+
 // To run main do: cargo run params, eg: cargo run 10 5 20 25 30
+// For Bézout coefficients: cargo run -- --bezout 240 46
+// For a modular inverse: cargo run -- --inverse 3 11
 fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mode = match args.first().map(String::as_str) {
+        Some(flag @ ("--bezout" | "--inverse")) => {
+            let flag = flag.to_string();
+            args.remove(0);
+            Some(flag)
+        }
+        _ => None,
+    };
+
+    let flag = match mode {
+        Some(flag) => flag,
+        None => {
+            print_gcd(&args);
+            return;
+        }
+    };
+
     let mut numbers = Vec::new();
 
-    for arg in env::args().skip(1){
-        numbers.push(u64::from_str(&arg).expect("Error parsing argument"));
+    for arg in &args {
+        numbers.push(u64::from_str(arg).expect("Error parsing argument"));
     }
 
-    if numbers.len() == 0 {
-        eprintln!("Usage: gcd NUMBER ...");
-        std::process::exit(1);
+    match (flag.as_str(), numbers.as_slice()) {
+        ("--bezout", &[a, b]) => {
+            let (g, x, y) = extended_gcd(a, b);
+            println!("gcd({}, {}) = {} = {} * {} + {} * {}", a, b, g, a, x, b, y);
+        }
+        ("--inverse", &[a, modulus]) => match mod_inverse(a, modulus) {
+            Some(inverse) => println!("The inverse of {} modulo {} is {}", a, modulus, inverse),
+            None => {
+                eprintln!("{} has no inverse modulo {}", a, modulus);
+                std::process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("{} takes exactly two numbers\n{}", flag, USAGE);
+            std::process::exit(1);
+        }
     }
-    let mut d = numbers[0];
-    for m in &numbers[1..]{
-        d = gcd(d, *m);
+}
+
+/// Print the greatest common divisor of `args`, which may be as large as
+/// you like. If they all fit in a `u64`, use plain `gcd`.
+fn print_gcd(args: &[String]) {
+    let mut numbers = Vec::new();
+
+    for arg in args {
+        numbers.push(BigUint::from_str(arg).expect("Error parsing argument"));
     }
+
+    if numbers.is_empty() {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+    let d = match numbers.iter().map(ToPrimitive::to_u64).collect::<Option<Vec<u64>>>() {
+        Some(small) => {
+            let mut d = small[0];
+            for m in &small[1..] {
+                d = gcd(d, *m);
+            }
+            BigUint::from(d)
+        }
+        None => {
+            let mut d = numbers[0].clone();
+            for m in &numbers[1..] {
+                d = big_gcd(d, m.clone());
+            }
+            d
+        }
+    };
     println!("The greatest common divisor of {:?} is {}", numbers, d);
 }
 
@@ -52,3 +163,42 @@ fn test_gcd() {
         assert_eq!(res, expected);
     }
 }
+
+#[test]
+fn test_big_gcd() {
+    let big = |s: &str| BigUint::from_str(s).unwrap();
+    // 2^127 - 1 and 2^89 - 1 are Mersenne primes.
+    let p = big("170141183460469231731687303715884105727");
+    let q = big("618970019642690137449562111");
+    assert_eq!(big_gcd(&p * &q * 6u32, &q * 15u32), &q * 3u32);
+    assert_eq!(big_gcd(p.clone(), q.clone()), BigUint::from(1u32));
+    assert_eq!(big_gcd(big("18"), big("12")), big("6"));
+}
+
+#[test]
+fn test_extended_gcd() {
+    let test_data: [(u64, u64); 5] = [
+        (240, 46),
+        (46, 240),
+        (14, 15),
+        (7, 0),
+        (u64::MAX, u64::MAX - 1),
+    ];
+    for &(a, b) in test_data.iter() {
+        let (g, x, y) = extended_gcd(a, b);
+        assert_eq!(g, if b == 0 { a } else { gcd(a, b) });
+        assert_eq!(a as i128 * x + b as i128 * y, g as i128);
+    }
+}
+
+#[test]
+fn test_mod_inverse() {
+    assert_eq!(mod_inverse(3, 11), Some(4));
+    assert_eq!(mod_inverse(25, 11), Some(4));
+    assert_eq!(mod_inverse(3, 12), None);
+    assert_eq!(mod_inverse(5, 1), Some(0));
+    assert_eq!(mod_inverse(5, 0), None);
+    let big = u64::MAX - 58; // the largest 64-bit prime
+    let inverse = mod_inverse(2, big).unwrap();
+    assert_eq!(2 * inverse as u128 % big as u128, 1);
+}
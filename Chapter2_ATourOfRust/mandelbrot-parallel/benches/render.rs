@@ -0,0 +1,76 @@
+//! Race every rendering kernel across some representative viewports.
+//!
+//! Run with `cargo bench`; Criterion writes its reports to
+//! `target/criterion`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use mandelbrot::KERNELS;
+use num::Complex;
+
+/// A viewport, and why it's interesting to time.
+struct Viewport {
+    name: &'static str,
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    limit: u32,
+}
+
+const BOUNDS: (usize, usize) = (400, 300);
+
+const VIEWPORTS: &[Viewport] = &[
+    // Mostly points that escape within a few iterations.
+    Viewport {
+        name: "overview",
+        upper_left: Complex { re: -2.0, im: 1.125 },
+        lower_right: Complex { re: 1.0, im: -1.125 },
+        limit: 255,
+    },
+    // Mostly points in the set, each of which runs to the limit.
+    Viewport {
+        name: "interior",
+        upper_left: Complex { re: -0.4, im: 0.3 },
+        lower_right: Complex { re: 0.0, im: 0.0 },
+        limit: 255,
+    },
+    // Boundary detail, where the work per pixel varies wildly: this is what
+    // splitting into bands has to cope with.
+    Viewport {
+        name: "seahorse-valley",
+        upper_left: Complex { re: -0.8, im: 0.2 },
+        lower_right: Complex { re: -0.7, im: 0.125 },
+        limit: 1000,
+    },
+    // The example from the book.
+    Viewport {
+        name: "book-example",
+        upper_left: Complex { re: -1.20, im: 0.35 },
+        lower_right: Complex { re: -1.0, im: 0.20 },
+        limit: 255,
+    },
+];
+
+fn bench_kernels(c: &mut Criterion) {
+    for viewport in VIEWPORTS {
+        let mut group = c.benchmark_group(viewport.name);
+        group.sample_size(10);
+        group.throughput(Throughput::Elements((BOUNDS.0 * BOUNDS.1) as u64));
+        for &(kernel_name, render) in KERNELS {
+            group.bench_function(BenchmarkId::from_parameter(kernel_name), |b| {
+                let mut counts = vec![0; BOUNDS.0 * BOUNDS.1];
+                b.iter(|| {
+                    render(
+                        &mut counts,
+                        BOUNDS,
+                        viewport.upper_left,
+                        viewport.lower_right,
+                        viewport.limit,
+                    )
+                });
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_kernels);
+criterion_main!(benches);
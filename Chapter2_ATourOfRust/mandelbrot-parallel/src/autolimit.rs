@@ -0,0 +1,123 @@
+use crate::cli::Options;
+use crate::render_parallel;
+use num::Complex;
+
+/// Ways to choose the iteration limit, instead of the user giving one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutoLimit {
+    /// Scale the limit with how far the viewport is zoomed in.
+    Zoom,
+    /// Start from the `Zoom` limit, and keep doubling it while that still
+    /// changes which points look like members of the set.
+    Adaptive,
+}
+
+/// The width of the viewport we treat as zoom level 1: the whole set.
+const FULL_WIDTH: f64 = 3.0;
+
+/// The smallest limit we ever choose, which is also what the book uses.
+const MIN_LIMIT: u32 = 255;
+
+/// We never choose a limit higher than this.
+const MAX_LIMIT: u32 = 1 << 20;
+
+/// The adaptive search stops once doubling the limit changes the fraction
+/// of pixels that reach it by less than this.
+const STABLE: f64 = 0.001;
+
+/// How big an image the adaptive search renders to try each limit.
+const PREVIEW_BOUNDS: (usize, usize) = (160, 120);
+
+/// If `options` asks for an automatic iteration limit, choose one and store
+/// it in `options.limit`, returning the chosen value.
+///
+/// Newton fractals have no escape counts to measure, so they always use
+/// the zoom-based limit.
+pub fn resolve(options: &mut Options) -> Option<u32> {
+    let limit = match options.auto_limit? {
+        AutoLimit::Adaptive if options.newton.is_none() => adaptive_limit(options),
+        _ => limit_for_zoom(options.upper_left, options.lower_right),
+    };
+    options.limit = limit;
+    options.auto_limit = None;
+    Some(limit)
+}
+
+/// Choose an iteration limit for the viewport with the given corners.
+///
+/// Deeper zooms show finer detail near the boundary of the set, where points
+/// take longer to escape, so the limit grows with the zoom: 255 for the
+/// whole set, about 720 at 10x, 1300 at 100x and 4700 at a million times.
+pub fn limit_for_zoom(upper_left: Complex<f64>, lower_right: Complex<f64>) -> u32 {
+    let zoom = FULL_WIDTH / (lower_right.re - upper_left.re);
+    let depth = zoom.log10().max(0.0);
+    let limit = MIN_LIMIT as f64 * (1.0 + depth).powf(1.5);
+    limit.min(MAX_LIMIT as f64) as u32
+}
+
+/// Find a limit high enough that raising it further barely changes the
+/// picture, by rendering a small preview of the viewport at the zoom-based
+/// limit, then at double that, and so on, until the fraction of pixels that
+/// never escape settles down.
+pub fn adaptive_limit(options: &Options) -> u32 {
+    let bounds = preview_bounds(options.bounds);
+    let mut counts = vec![0; bounds.0 * bounds.1];
+    let mut interior_at = |limit: u32| {
+        render_parallel(&mut counts, bounds, options.upper_left, options.lower_right, limit, options.threads);
+        counts.iter().filter(|&&count| count >= limit).count() as f64 / counts.len() as f64
+    };
+
+    let mut limit = limit_for_zoom(options.upper_left, options.lower_right);
+    let mut fraction = interior_at(limit);
+    while limit < MAX_LIMIT {
+        let next = limit.saturating_mul(2).min(MAX_LIMIT);
+        let next_fraction = interior_at(next);
+        if (fraction - next_fraction).abs() < STABLE {
+            break;
+        }
+        limit = next;
+        fraction = next_fraction;
+    }
+    limit
+}
+
+/// Shrink `bounds` to fit within `PREVIEW_BOUNDS`, keeping its shape.
+fn preview_bounds(bounds: (usize, usize)) -> (usize, usize) {
+    let scale = (PREVIEW_BOUNDS.0 as f64 / bounds.0 as f64)
+        .min(PREVIEW_BOUNDS.1 as f64 / bounds.1 as f64)
+        .min(1.0);
+    (
+        ((bounds.0 as f64 * scale) as usize).max(1),
+        ((bounds.1 as f64 * scale) as usize).max(1),
+    )
+}
+
+#[test]
+fn test_limit_for_zoom() {
+    let whole = limit_for_zoom(Complex { re: -2.0, im: 1.125 }, Complex { re: 1.0, im: -1.125 });
+    assert_eq!(whole, 255);
+    let closer = limit_for_zoom(Complex { re: -0.75, im: 0.1 }, Complex { re: -0.72, im: 0.08 });
+    assert_eq!(closer, 1325);
+    let deep = limit_for_zoom(Complex { re: -0.75, im: 0.1 }, Complex { re: -0.75 + 3e-6, im: 0.1 - 2e-6 });
+    assert!((4700..4750).contains(&deep), "limit {}", deep);
+}
+
+#[test]
+fn test_adaptive_limit() {
+    let options = Options {
+        bounds: (40, 30),
+        upper_left: Complex { re: -0.8, im: 0.2 },
+        lower_right: Complex { re: -0.7, im: 0.125 },
+        threads: 1,
+        auto_limit: Some(AutoLimit::Adaptive),
+        ..Options::default()
+    };
+    let limit = adaptive_limit(&options);
+    let start = limit_for_zoom(options.upper_left, options.lower_right);
+    assert!(limit >= start && limit.is_multiple_of(start));
+
+    let mut options = options;
+    assert_eq!(resolve(&mut options), Some(limit));
+    assert_eq!((options.limit, options.auto_limit), (limit, None));
+    assert_eq!(resolve(&mut options), None);
+}
@@ -0,0 +1,334 @@
+use crate::autolimit;
+use crate::cli::{self, CliError, Options};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// A job file is TOML. Each `[[render]]` table describes one render, using
+// the long names of the command-line options as keys; values are written
+// the same way as on the command line, except that numbers may be left
+// unquoted. An optional `[defaults]` table applies to every render.
+//
+//     [defaults]
+//     size = "1600x1200"
+//     iterations = 1000
+//     palette = "fire"
+//
+//     [[render]]
+//     output = "overview.png"
+//
+//     [[render]]
+//     output = "seahorse-valley.png"
+//     upper-left = "-0.8,0.2"
+//     lower-right = "-0.7,0.1"
+
+/// Why a job file could not be loaded.
+#[derive(Debug)]
+pub enum BatchError {
+    Io(io::Error),
+    Syntax(toml::de::Error),
+    /// A top-level key other than `defaults` or `render`, or one of those
+    /// with the wrong shape.
+    BadStructure(String),
+    /// `location` names the table the problem is in, like `render 3`.
+    UnknownKey { location: String, key: String },
+    BadValue { location: String, error: CliError },
+    MissingOutput { location: String },
+    DuplicateOutput { location: String, output: String },
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatchError::Io(e) => write!(f, "{}", e),
+            BatchError::Syntax(e) => write!(f, "{}", e),
+            BatchError::BadStructure(message) => f.write_str(message),
+            BatchError::UnknownKey { location, key } => {
+                write!(f, "{}: unknown key '{}'", location, key)
+            }
+            BatchError::BadValue { location, error } => write!(f, "{}: {}", location, error),
+            BatchError::MissingOutput { location } => {
+                write!(f, "{}: every render needs an 'output'", location)
+            }
+            BatchError::DuplicateOutput { location, output } => {
+                write!(f, "{}: '{}' is already written by an earlier render", location, output)
+            }
+        }
+    }
+}
+
+/// Read the job file named `filename` and return the options for each
+/// render it lists, in order.
+pub fn load_job_file(filename: &str, defaults: &Options) -> Result<Vec<Options>, BatchError> {
+    let text = fs::read_to_string(filename).map_err(BatchError::Io)?;
+    parse_jobs(&text, defaults)
+}
+
+/// Parse the text of a job file. Anything a render doesn't set comes from
+/// the file's `[defaults]` table, and failing that from `defaults`.
+fn parse_jobs(text: &str, defaults: &Options) -> Result<Vec<Options>, BatchError> {
+    let table: toml::Table = text.parse().map_err(BatchError::Syntax)?;
+
+    let mut base = defaults.clone();
+    let mut renders = None;
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("defaults", toml::Value::Table(defaults)) => {
+                apply_table(&mut base, defaults, "defaults")?
+            }
+            ("render", toml::Value::Array(array)) => renders = Some(array),
+            ("defaults", _) | ("render", _) => {
+                return Err(BatchError::BadStructure(format!(
+                    "'{}' should be written as [{}]",
+                    key,
+                    if key == "render" { "[render]" } else { "defaults" }
+                )));
+            }
+            _ => {
+                return Err(BatchError::BadStructure(format!(
+                    "unexpected top-level key '{}'",
+                    key
+                )));
+            }
+        }
+    }
+
+    let renders = match renders {
+        Some(renders) if !renders.is_empty() => renders,
+        _ => {
+            return Err(BatchError::BadStructure(
+                "the file lists no [[render]] tables".to_string(),
+            ));
+        }
+    };
+
+    let mut outputs = HashSet::new();
+    let mut jobs = Vec::with_capacity(renders.len());
+    for (i, render) in renders.into_iter().enumerate() {
+        let location = format!("render {}", i + 1);
+        let render = match render {
+            toml::Value::Table(render) => render,
+            _ => {
+                return Err(BatchError::BadStructure(format!(
+                    "{} is not a table",
+                    location
+                )));
+            }
+        };
+        if !render.contains_key("output") {
+            return Err(BatchError::MissingOutput { location });
+        }
+
+        let mut options = base.clone();
+        apply_table(&mut options, render, &location)?;
+        cli::check_options(&options).map_err(|error| BatchError::BadValue {
+            location: location.clone(),
+            error,
+        })?;
+        if !outputs.insert(options.output.clone()) {
+            return Err(BatchError::DuplicateOutput {
+                location,
+                output: options.output,
+            });
+        }
+        jobs.push(options);
+    }
+    Ok(jobs)
+}
+
+/// Set each option named by a key of `table`, exactly as if it had been
+/// given on the command line as `--key value`.
+fn apply_table(options: &mut Options, table: toml::Table, location: &str) -> Result<(), BatchError> {
+    for (key, value) in table {
+        let text = match value {
+            toml::Value::String(s) => s,
+            toml::Value::Integer(n) => n.to_string(),
+            toml::Value::Float(x) => x.to_string(),
+            _ => {
+                return Err(BatchError::BadValue {
+                    location: location.to_string(),
+                    error: CliError::OutOfRange {
+                        flag: key,
+                        value: value.to_string(),
+                        reason: "expected a string or a number",
+                    },
+                });
+            }
+        };
+        cli::set_option(options, &format!("--{}", key), text).map_err(|error| match error {
+            CliError::UnknownFlag(_) => BatchError::UnknownKey {
+                location: location.to_string(),
+                key: key.clone(),
+            },
+            error => BatchError::BadValue {
+                location: location.to_string(),
+                error,
+            },
+        })?;
+    }
+    Ok(())
+}
+
+/// How one render went.
+pub struct Outcome {
+    pub options: Options,
+    pub elapsed: Duration,
+    pub result: Result<(), io::Error>,
+}
+
+/// The outcome of every job in a batch, in the order they were listed.
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+    pub elapsed: Duration,
+}
+
+/// Render every job in `jobs`, running up to `concurrency` of them at once.
+///
+/// A job that fails doesn't stop the others; its error is recorded in the
+/// report instead.
+pub fn run(jobs: Vec<Options>, concurrency: usize) -> Report {
+    let start = Instant::now();
+    let total = jobs.len();
+    let next = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+
+    let mut outcomes: Vec<(usize, Outcome)> = crossbeam::scope(|spawner| {
+        let workers: Vec<_> = (0..concurrency.min(total))
+            .map(|_| {
+                spawner.spawn(|_| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        if index >= total {
+                            return done;
+                        }
+                        let mut options = jobs[index].clone();
+                        let job_start = Instant::now();
+                        autolimit::resolve(&mut options);
+                        let result = crate::render_to_file(&options);
+                        let count = finished.fetch_add(1, Ordering::SeqCst) + 1;
+                        println!(
+                            "[{}/{}] {} {}",
+                            count,
+                            total,
+                            if result.is_ok() { "wrote" } else { "FAILED" },
+                            options.output
+                        );
+                        done.push((
+                            index,
+                            Outcome {
+                                options,
+                                elapsed: job_start.elapsed(),
+                                result,
+                            },
+                        ));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+    .unwrap();
+
+    outcomes.sort_by_key(|&(index, _)| index);
+    Report {
+        outcomes: outcomes.into_iter().map(|(_, outcome)| outcome).collect(),
+        elapsed: start.elapsed(),
+    }
+}
+
+impl Report {
+    pub fn failures(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.result.is_err()).count()
+    }
+
+    /// Print a table of the jobs, followed by a one-line summary.
+    pub fn print(&self) {
+        println!();
+        println!("{:>4}  {:<6}  {:>11}  {:>6}  {:>9}  output", "job", "status", "size", "limit", "time");
+        for (i, outcome) in self.outcomes.iter().enumerate() {
+            let options = &outcome.options;
+            println!(
+                "{:>4}  {:<6}  {:>11}  {:>6}  {:>8.2}s  {}",
+                i + 1,
+                if outcome.result.is_ok() { "ok" } else { "failed" },
+                format!("{}x{}", options.bounds.0, options.bounds.1),
+                options.limit,
+                outcome.elapsed.as_secs_f64(),
+                options.output
+            );
+            if let Err(e) = &outcome.result {
+                println!("{:>6}{}", "", e);
+            }
+        }
+        println!(
+            "{} rendered, {} failed in {:.2}s",
+            self.outcomes.len() - self.failures(),
+            self.failures(),
+            self.elapsed.as_secs_f64()
+        );
+    }
+}
+
+#[test]
+fn test_parse_jobs() {
+    let defaults = Options::default();
+    let jobs = parse_jobs(
+        r#"
+            [defaults]
+            size = "400x300"
+            iterations = 1000
+
+            [[render]]
+            output = "a.png"
+
+            [[render]]
+            output = "b.stl"
+            upper-left = "-1.20,0.35"
+            lower-right = "-1,0.20"
+            palette = "fire"
+            height-scale = 10
+        "#,
+        &defaults,
+    )
+    .unwrap();
+
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(jobs[0].output, "a.png");
+    assert_eq!(jobs[0].bounds, (400, 300));
+    assert_eq!(jobs[0].limit, 1000);
+    assert_eq!(jobs[0].upper_left, defaults.upper_left);
+    assert_eq!(jobs[1].bounds, (400, 300));
+    assert_eq!(jobs[1].upper_left, num::Complex { re: -1.20, im: 0.35 });
+    assert_eq!(jobs[1].height_scale, 10.0);
+}
+
+#[test]
+fn test_parse_jobs_errors() {
+    let defaults = Options::default();
+    let error = |text: &str| parse_jobs(text, &defaults).unwrap_err().to_string();
+
+    assert_eq!(
+        error("[[render]]\noutput = \"a.png\"\nsize = \"10y10\"\n"),
+        "render 1: invalid value '10y10' for '--size': expected two values separated by 'x'"
+    );
+    assert_eq!(
+        error("[[render]]\noutput = \"a.png\"\ncolour = \"red\"\n"),
+        "render 1: unknown key 'colour'"
+    );
+    assert_eq!(
+        error("[[render]]\noutput = \"a.png\"\n[[render]]\nsize = \"10x10\"\n"),
+        "render 2: every render needs an 'output'"
+    );
+    assert_eq!(
+        error("[[render]]\noutput = \"a.png\"\n[[render]]\noutput = \"a.png\"\n"),
+        "render 2: 'a.png' is already written by an earlier render"
+    );
+    assert_eq!(error("[defaults]\n"), "the file lists no [[render]] tables");
+}
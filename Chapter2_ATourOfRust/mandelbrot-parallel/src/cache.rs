@@ -0,0 +1,218 @@
+use crate::cli::Options;
+use crate::mesh::MeshFormat;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A directory of finished outputs and raw escape counts, named by a hash of
+/// everything that went into making them.
+///
+/// Rendering the same viewport again just copies the earlier output; if
+/// only the palette or mesh settings changed, the cached escape counts are
+/// colored again without rendering. When the files in the directory add up
+/// to more than `capacity` bytes, the least recently used ones are deleted.
+/// Each entry's modification time records when it was last used, so several
+/// processes can share one cache directory.
+pub struct Cache {
+    dir: PathBuf,
+    capacity: u64,
+}
+
+/// Bump this whenever the rendering code changes in a way that alters its
+/// output, so that stale entries are never used.
+const CACHE_VERSION: u32 = 1;
+
+impl Cache {
+    pub fn open<P: AsRef<Path>>(dir: P, capacity: u64) -> io::Result<Cache> {
+        fs::create_dir_all(&dir)?;
+        Ok(Cache {
+            dir: dir.as_ref().to_path_buf(),
+            capacity,
+        })
+    }
+
+    /// If the cache has an entry named `name`, mark it as just used and
+    /// return its path.
+    pub fn lookup(&self, name: &str) -> Option<PathBuf> {
+        let path = self.dir.join(name);
+        let file = File::options().append(true).open(&path).ok()?;
+        // A failure here only makes eviction less accurate.
+        let _ = file.set_modified(SystemTime::now());
+        Some(path)
+    }
+
+    /// Store `data` as the entry `name`, and then evict old entries if the
+    /// cache has grown too big.
+    pub fn insert(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        // Write under a temporary name and rename it into place, so that no
+        // one ever sees a half-written entry.
+        let temporary = self.dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+        fs::write(&temporary, data)?;
+        fs::rename(&temporary, self.dir.join(name))?;
+        self.evict()
+    }
+
+    /// Delete the least recently used entries until the cache fits within
+    /// its capacity.
+    pub fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                total += metadata.len();
+                entries.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.capacity {
+                break;
+            }
+            // Another process may have beaten us to it.
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the cache entry holding the escape counts for `options`.
+///
+/// This covers only what affects the counts: the formula, the size, the
+/// corners and the iteration limit. Newton fractals have no escape counts, and
+/// only their finished images are cached.
+pub fn counts_name(options: &Options) -> String {
+    format!("{}.counts", hash(&counts_description(options)))
+}
+
+/// The name of the cache entry holding the finished output for `options`.
+pub fn output_name(options: &Options) -> String {
+    let mut description = counts_description(options);
+    let extension = match MeshFormat::from_filename(&options.output) {
+        Some(format) => {
+            description.push_str(&format!(
+                " mesh={:?} height-scale={:08x} smooth={}",
+                format,
+                options.height_scale.to_bits(),
+                options.smoothing
+            ));
+            Path::new(&options.output)
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("mesh")
+                .to_ascii_lowercase()
+        }
+        None if options.newton.is_some() => "png".to_string(),
+        None => {
+            description.push_str(&format!(" image=png palette={}", options.palette));
+            "png".to_string()
+        }
+    };
+    format!("{}.{}", hash(&description), extension)
+}
+
+/// Spell out the parameters that determine the escape counts, using the
+/// exact bits of the coordinates so that nearby viewports never collide.
+fn counts_description(options: &Options) -> String {
+    let formula = match &options.newton {
+        Some(polynomial) => format!("newton({})", polynomial),
+        None => "mandelbrot".to_string(),
+    };
+    format!(
+        "v{} formula={} size={}x{} upper-left={:016x},{:016x} lower-right={:016x},{:016x} limit={}",
+        CACHE_VERSION,
+        formula,
+        options.bounds.0,
+        options.bounds.1,
+        options.upper_left.re.to_bits(),
+        options.upper_left.im.to_bits(),
+        options.lower_right.re.to_bits(),
+        options.lower_right.im.to_bits(),
+        options.limit
+    )
+}
+
+fn hash(description: &str) -> String {
+    Sha256::digest(description.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Turn escape counts into the bytes we store in the cache.
+pub fn encode_counts(counts: &[u32]) -> Vec<u8> {
+    counts.iter().flat_map(|count| count.to_le_bytes()).collect()
+}
+
+/// Read back escape counts stored by `encode_counts`, checking that there
+/// are exactly `pixels` of them.
+pub fn decode_counts(bytes: &[u8], pixels: usize) -> Option<Vec<u32>> {
+    if bytes.len() != pixels * 4 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_names() {
+    use crate::palette::Palette;
+
+    let options = Options::default();
+    let recolored = Options {
+        palette: Palette::Fire,
+        ..Options::default()
+    };
+    let deeper = Options {
+        limit: 1000,
+        ..Options::default()
+    };
+    assert_eq!(counts_name(&options), counts_name(&recolored));
+    assert_ne!(output_name(&options), output_name(&recolored));
+    assert_ne!(counts_name(&options), counts_name(&deeper));
+    assert!(output_name(&options).ends_with(".png"));
+}
+
+#[test]
+fn test_evicts_least_recently_used() {
+    let dir = std::env::temp_dir().join(format!("mandelbrot-cache-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let cache = Cache::open(&dir, 25).unwrap();
+    // Date entries explicitly, rather than sleeping between uses and hoping
+    // the file system keeps times finely enough to tell them apart.
+    let last_used = |name: &str, minutes_ago: u64| {
+        let time = SystemTime::now() - std::time::Duration::from_secs(60 * minutes_ago);
+        File::options().append(true).open(dir.join(name)).unwrap().set_modified(time).unwrap();
+    };
+
+    cache.insert("a", &[0; 10]).unwrap();
+    last_used("a", 20);
+    cache.insert("b", &[0; 10]).unwrap();
+    last_used("b", 10);
+    // Using "a" makes it the most recently used.
+    assert!(cache.lookup("a").is_some());
+    // This pushes the total over 25 bytes, so "b", the least recently
+    // used, has to go.
+    cache.insert("c", &[0; 10]).unwrap();
+
+    assert!(cache.lookup("a").is_some());
+    assert!(cache.lookup("b").is_none());
+    assert!(cache.lookup("c").is_some());
+    fs::remove_dir_all(&dir).unwrap();
+}
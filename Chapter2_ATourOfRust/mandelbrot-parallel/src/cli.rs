@@ -0,0 +1,586 @@
+use crate::autolimit::AutoLimit;
+use crate::mesh::MeshFormat;
+use crate::newton::{Polynomial, PolynomialError};
+use crate::palette::{PALETTE_NAMES, Palette};
+use crate::{ParseError, parse_complex, parse_pair};
+use num::Complex;
+use std::env;
+use std::fmt;
+use std::num::{ParseFloatError, ParseIntError};
+
+/// Everything needed to render one image, as given on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub bounds: (usize, usize),
+    pub upper_left: Complex<f64>,
+    pub lower_right: Complex<f64>,
+    pub limit: u32,
+    /// If set, `limit` is only a placeholder until `autolimit::resolve`
+    /// chooses one.
+    pub auto_limit: Option<AutoLimit>,
+    pub threads: usize,
+    pub palette: Palette,
+    pub output: String,
+    /// When `output` is a mesh file, the height of the points in the set.
+    pub height_scale: f32,
+    /// When `output` is a mesh file, how many times to blur the heights.
+    pub smoothing: usize,
+    /// Where to keep the render cache, if anywhere.
+    pub cache_dir: Option<String>,
+    /// How big the render cache may grow, in bytes.
+    pub cache_size: u64,
+    /// Draw the Newton fractal for this polynomial instead of the
+    /// Mandelbrot set.
+    pub newton: Option<Polynomial>,
+}
+
+const MEBIBYTE: u64 = 1024 * 1024;
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            bounds: (1000, 750),
+            upper_left: Complex { re: -2.0, im: 1.125 },
+            lower_right: Complex { re: 1.0, im: -1.125 },
+            limit: 255,
+            auto_limit: None,
+            threads: num_cpus::get(),
+            palette: Palette::Grayscale,
+            output: "mandel.png".to_string(),
+            height_scale: 50.0,
+            smoothing: 0,
+            cache_dir: env::var("MANDELBROT_CACHE_DIR").ok().filter(|dir| !dir.is_empty()),
+            cache_size: 1024 * MEBIBYTE,
+            newton: None,
+        }
+    }
+}
+
+/// What the user asked us to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Render(Options),
+    /// Render every job listed in `file`, `jobs` at a time. Options given on
+    /// the command line serve as defaults for the jobs.
+    Batch {
+        file: String,
+        jobs: usize,
+        defaults: Options,
+    },
+    /// Render the viewport in `options` and print statistics about it as
+    /// JSON, along with an estimate of the set's area from `samples` random
+    /// points.
+    Analyze {
+        options: Options,
+        samples: u64,
+        seed: u64,
+    },
+    Help,
+}
+
+/// Why the command line could not be understood.
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    UnknownFlag(String),
+    MissingValue(String),
+    UnexpectedArgument(String),
+    BadPair {
+        flag: String,
+        value: String,
+        error: ParseError,
+    },
+    BadNumber {
+        flag: String,
+        value: String,
+        error: ParseIntError,
+    },
+    BadFloat {
+        flag: String,
+        value: String,
+        error: ParseFloatError,
+    },
+    BadPalette(String),
+    BadPolynomial {
+        flag: String,
+        value: String,
+        error: PolynomialError,
+    },
+    /// The value parsed, but makes no sense for this flag.
+    OutOfRange {
+        flag: String,
+        value: String,
+        reason: &'static str,
+    },
+    /// Two options that can't be used together.
+    Conflict(&'static str, &'static str),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::UnknownFlag(flag) => write!(f, "unknown option '{}'", flag),
+            CliError::MissingValue(flag) => write!(f, "option '{}' needs a value", flag),
+            CliError::UnexpectedArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            CliError::BadPair { flag, value, error } => {
+                write!(f, "invalid value '{}' for '{}': {}", value, flag, error)
+            }
+            CliError::BadNumber { flag, value, error } => {
+                write!(f, "invalid value '{}' for '{}': {}", value, flag, error)
+            }
+            CliError::BadFloat { flag, value, error } => {
+                write!(f, "invalid value '{}' for '{}': {}", value, flag, error)
+            }
+            CliError::BadPolynomial { flag, value, error } => {
+                write!(f, "invalid value '{}' for '{}': {}", value, flag, error)
+            }
+            CliError::BadPalette(value) => write!(
+                f,
+                "unknown palette '{}' (expected one of: {})",
+                value, PALETTE_NAMES
+            ),
+            CliError::OutOfRange {
+                flag,
+                value,
+                reason,
+            } => write!(f, "invalid value '{}' for '{}': {}", value, flag, reason),
+            CliError::Conflict(flag, other) => write!(f, "'{}' can't be used with '{}'", flag, other),
+        }
+    }
+}
+
+/// The most pixels we'll render, so that a typo in `--size` gets an error
+/// rather than an attempt to allocate more memory than there is.
+const MAX_PIXELS: usize = 1 << 28;
+
+/// The options that take a value.
+enum Field {
+    Size,
+    UpperLeft,
+    LowerRight,
+    Iterations,
+    Threads,
+    Palette,
+    Output,
+    HeightScale,
+    Smoothing,
+    CacheDir,
+    CacheSize,
+    Newton,
+}
+
+impl Field {
+    fn from_flag(flag: &str) -> Option<Field> {
+        match flag {
+            "-s" | "--size" => Some(Field::Size),
+            "-u" | "--upper-left" => Some(Field::UpperLeft),
+            "-l" | "--lower-right" => Some(Field::LowerRight),
+            "-i" | "--iterations" => Some(Field::Iterations),
+            "-t" | "--threads" => Some(Field::Threads),
+            "-p" | "--palette" => Some(Field::Palette),
+            "-o" | "--output" => Some(Field::Output),
+            "--height-scale" => Some(Field::HeightScale),
+            "--smooth" => Some(Field::Smoothing),
+            "--cache-dir" => Some(Field::CacheDir),
+            "--cache-size" => Some(Field::CacheSize),
+            "--newton" => Some(Field::Newton),
+            _ => None,
+        }
+    }
+}
+
+pub fn print_usage(program: &str) {
+    println!("Usage: {} [OPTIONS]", program);
+    println!();
+    println!("Render a region of the Mandelbrot set to a PNG image or a 3D mesh.");
+    println!();
+    println!("Options:");
+    println!("  -s, --size WxH             image size in pixels [default: 1000x750]");
+    println!("  -u, --upper-left RE,IM     upper left corner of the viewport [default: -2.0,1.125]");
+    println!("  -l, --lower-right RE,IM    lower right corner of the viewport [default: 1.0,-1.125]");
+    println!("  -i, --iterations N         iteration limit per point [default: 255]; 'auto' picks");
+    println!("                             one from the zoom depth, and 'adaptive' keeps doubling");
+    println!("                             that until the points in the set stop changing");
+    println!("  -t, --threads N            number of rendering threads [default: number of CPUs]");
+    println!("  -p, --palette NAME         one of: {} [default: grayscale]", PALETTE_NAMES);
+    println!("  -o, --output FILE          file to write [default: mandel.png]");
+    println!("                             a .obj, .stl or .gltf extension writes a 3D heightmap");
+    println!("                             of the escape counts instead of an image");
+    println!("      --height-scale H       mesh height of points in the set [default: 50]");
+    println!("      --smooth N             mesh smoothing passes [default: 0]");
+    println!("      --newton COEFFS        draw the Newton fractal of the polynomial with these");
+    println!("                             comma-separated real coefficients, highest power first,");
+    println!("                             coloring each point by the root it converges to");
+    println!("                             (e.g. 1,0,0,-1 for z^3 - 1); ignores --palette");
+    println!("      --cache-dir DIR        reuse earlier renders saved in DIR, and save this one");
+    println!("                             [default: $MANDELBROT_CACHE_DIR, if set]");
+    println!("      --cache-size MB        evict the least recently used renders when the cache");
+    println!("                             grows past this size [default: 1024]");
+    println!("      --batch FILE           render every job listed in a TOML job file; the other");
+    println!("                             options give defaults for the jobs");
+    println!("  -j, --jobs N               with --batch, how many jobs to render at once [default: 1]");
+    println!("      --analyze              print statistics about the render as JSON instead of");
+    println!("                             writing a file");
+    println!("      --samples N            with --analyze, random points used to estimate the");
+    println!("                             area of the set [default: 1000000]");
+    println!("      --seed N               with --analyze, seed for the random points [default: 1]");
+    println!("  -h, --help                 print this message");
+    println!();
+    println!(
+        "Example: {} -o mandel.png -s 4000x3000 -u -1.20,0.35 -l -1,0.20",
+        program
+    );
+}
+
+/// Parse the command-line arguments, not including the program name.
+///
+/// Options may be written either as `--size 800x600` or `--size=800x600`.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut batch = None;
+    let mut jobs = 1;
+    let mut threads_given = false;
+    let mut analyze = false;
+    let mut samples = 1_000_000;
+    let mut seed = 1;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(arg)?;
+
+        if flag == "-h" || flag == "--help" {
+            return Ok(Command::Help);
+        }
+        if flag == "--analyze" {
+            if let Some(value) = inline_value {
+                return Err(CliError::UnexpectedArgument(value));
+            }
+            analyze = true;
+            continue;
+        }
+        let is_command_flag = matches!(
+            flag.as_str(),
+            "--batch" | "-j" | "--jobs" | "--samples" | "--seed"
+        );
+        if !is_command_flag && Field::from_flag(&flag).is_none() {
+            return Err(CliError::UnknownFlag(flag));
+        }
+
+        // Negative coordinates like `-1.20,0.35` look like flags, so the value
+        // of an option is always taken from the next argument verbatim.
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(CliError::MissingValue(flag)),
+        };
+
+        match flag.as_str() {
+            "--batch" => batch = Some(value),
+            "-j" | "--jobs" => {
+                jobs = number(&flag, &value)?;
+                if jobs == 0 {
+                    return Err(out_of_range(&flag, &value, "must be at least 1"));
+                }
+            }
+            "--samples" => {
+                samples = number(&flag, &value)?;
+                if samples == 0 {
+                    return Err(out_of_range(&flag, &value, "must be at least 1"));
+                }
+            }
+            "--seed" => seed = number(&flag, &value)?,
+            _ => {
+                threads_given |= matches!(flag.as_str(), "-t" | "--threads");
+                set_option(&mut options, &flag, value)?
+            }
+        }
+    }
+
+    if let Some(file) = batch {
+        if analyze {
+            return Err(CliError::Conflict("--analyze", "--batch"));
+        }
+        // Unless told otherwise, share the CPUs among the jobs running at once.
+        if !threads_given {
+            options.threads = (options.threads / jobs).max(1);
+        }
+        return Ok(Command::Batch {
+            file,
+            jobs,
+            defaults: options,
+        });
+    }
+
+    check_options(&options)?;
+    if let (true, Some(polynomial)) = (analyze, &options.newton) {
+        return Err(CliError::OutOfRange {
+            flag: "--newton".to_string(),
+            value: polynomial.to_string(),
+            reason: "--analyze only works with the Mandelbrot set",
+        });
+    }
+    if analyze {
+        return Ok(Command::Analyze {
+            options,
+            samples,
+            seed,
+        });
+    }
+    Ok(Command::Render(options))
+}
+
+/// Set the field of `options` named by `flag` from the text `value`,
+/// checking that the value makes sense on its own.
+pub fn set_option(options: &mut Options, flag: &str, value: String) -> Result<(), CliError> {
+    let field = match Field::from_flag(flag) {
+        Some(field) => field,
+        None => return Err(CliError::UnknownFlag(flag.to_string())),
+    };
+
+    match field {
+        Field::Size => options.bounds = parse_size(flag, &value)?,
+        Field::UpperLeft => options.upper_left = parse_corner(flag, &value)?,
+        Field::LowerRight => options.lower_right = parse_corner(flag, &value)?,
+        Field::Iterations => match value.as_str() {
+            "auto" => options.auto_limit = Some(AutoLimit::Zoom),
+            "adaptive" => options.auto_limit = Some(AutoLimit::Adaptive),
+            _ => {
+                options.limit = number(flag, &value)?;
+                options.auto_limit = None;
+                if options.limit == 0 {
+                    return Err(out_of_range(flag, &value, "must be at least 1"));
+                }
+            }
+        },
+        Field::Threads => {
+            options.threads = number(flag, &value)?;
+            if options.threads == 0 {
+                return Err(out_of_range(flag, &value, "must be at least 1"));
+            }
+        }
+        Field::Palette => options.palette = parse_palette(value)?,
+        Field::Output => options.output = value,
+        Field::HeightScale => {
+            options.height_scale = value.parse().map_err(|error| CliError::BadFloat {
+                flag: flag.to_string(),
+                value: value.clone(),
+                error,
+            })?;
+            if !(options.height_scale.is_finite() && options.height_scale > 0.0) {
+                return Err(out_of_range(flag, &value, "must be a positive number"));
+            }
+        }
+        Field::Smoothing => options.smoothing = number(flag, &value)?,
+        Field::CacheDir => options.cache_dir = Some(value).filter(|dir| !dir.is_empty()),
+        Field::CacheSize => {
+            let megabytes: u64 = number(flag, &value)?;
+            options.cache_size = megabytes.saturating_mul(MEBIBYTE);
+        }
+        Field::Newton => {
+            let polynomial = value.parse().map_err(|error| CliError::BadPolynomial {
+                flag: flag.to_string(),
+                value: value.clone(),
+                error,
+            })?;
+            options.newton = Some(polynomial);
+        }
+    }
+    Ok(())
+}
+
+/// Check the constraints between fields of `options` that `set_option`
+/// can't check one value at a time.
+pub fn check_options(options: &Options) -> Result<(), CliError> {
+    check_viewport(options.upper_left, options.lower_right)?;
+
+    if options.newton.is_some() && MeshFormat::from_filename(&options.output).is_some() {
+        return Err(CliError::OutOfRange {
+            flag: "--output".to_string(),
+            value: options.output.clone(),
+            reason: "Newton fractals can only be written as images",
+        });
+    }
+
+    if MeshFormat::from_filename(&options.output).is_some()
+        && (options.bounds.0 < 2 || options.bounds.1 < 2)
+    {
+        return Err(CliError::OutOfRange {
+            flag: "--size".to_string(),
+            value: format!("{}x{}", options.bounds.0, options.bounds.1),
+            reason: "a mesh needs at least 2x2 pixels",
+        });
+    }
+
+    Ok(())
+}
+
+// The single-threaded program in `../mandelbrot` takes fewer options, but
+// parses the ones it has with the functions from here on.
+
+/// Split an argument like `--size=800x600` into the flag and the value
+/// written with it, if any. Anything that isn't a flag is an error.
+pub fn split_flag(arg: String) -> Result<(String, Option<String>), CliError> {
+    if !arg.starts_with('-') || arg == "-" {
+        return Err(CliError::UnexpectedArgument(arg));
+    }
+    Ok(match arg.split_once('=') {
+        Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+        None => (arg, None),
+    })
+}
+
+/// The value of `--size`: a width and height, neither of them zero, and
+/// not too many pixels in all.
+pub fn parse_size(flag: &str, value: &str) -> Result<(usize, usize), CliError> {
+    let bounds = pair(flag, value, parse_pair::<usize>(value, 'x'))?;
+    if bounds.0 == 0 || bounds.1 == 0 {
+        return Err(out_of_range(flag, value, "width and height must be non-zero"));
+    }
+    if bounds.0.checked_mul(bounds.1).is_none_or(|pixels| pixels > MAX_PIXELS) {
+        return Err(out_of_range(flag, value, "the image can have at most 2^28 pixels"));
+    }
+    Ok(bounds)
+}
+
+/// The value of `--upper-left` or `--lower-right`.
+pub fn parse_corner(flag: &str, value: &str) -> Result<Complex<f64>, CliError> {
+    pair(flag, value, parse_complex(value))
+}
+
+pub fn parse_palette(value: String) -> Result<Palette, CliError> {
+    value.parse().map_err(|_| CliError::BadPalette(value))
+}
+
+/// Check that `upper_left` really is above and to the left of
+/// `lower_right`.
+pub fn check_viewport(upper_left: Complex<f64>, lower_right: Complex<f64>) -> Result<(), CliError> {
+    if upper_left.re >= lower_right.re || upper_left.im <= lower_right.im {
+        return Err(CliError::OutOfRange {
+            flag: "--upper-left".to_string(),
+            value: format!("{},{}", upper_left.re, upper_left.im),
+            reason: "must lie above and to the left of --lower-right",
+        });
+    }
+    Ok(())
+}
+
+fn pair<T>(flag: &str, value: &str, parsed: Result<T, ParseError>) -> Result<T, CliError> {
+    parsed.map_err(|error| CliError::BadPair {
+        flag: flag.to_string(),
+        value: value.to_string(),
+        error,
+    })
+}
+
+pub fn number<T: std::str::FromStr<Err = ParseIntError>>(flag: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|error| CliError::BadNumber {
+        flag: flag.to_string(),
+        value: value.to_string(),
+        error,
+    })
+}
+
+pub fn out_of_range(flag: &str, value: &str, reason: &'static str) -> CliError {
+    CliError::OutOfRange {
+        flag: flag.to_string(),
+        value: value.to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_parse_args() {
+    let command = parse_args(args(&[
+        "-s", "400x300", "--upper-left=-1.20,0.35", "-l", "-1,0.20", "-i", "1000", "-t", "2",
+        "-p", "fire", "-o", "out.png",
+    ]));
+    assert_eq!(
+        command,
+        Ok(Command::Render(Options {
+            bounds: (400, 300),
+            upper_left: Complex { re: -1.20, im: 0.35 },
+            lower_right: Complex { re: -1.0, im: 0.20 },
+            limit: 1000,
+            threads: 2,
+            palette: Palette::Fire,
+            output: "out.png".to_string(),
+            height_scale: 50.0,
+            smoothing: 0,
+            ..Options::default()
+        }))
+    );
+    assert_eq!(parse_args(args(&["-s", "10x10", "--help"])), Ok(Command::Help));
+    assert_eq!(
+        parse_args(args(&["-i", "adaptive"])),
+        Ok(Command::Render(Options {
+            auto_limit: Some(AutoLimit::Adaptive),
+            ..Options::default()
+        }))
+    );
+    assert_eq!(
+        parse_args(args(&["--analyze", "--samples", "5000"])),
+        Ok(Command::Analyze {
+            options: Options::default(),
+            samples: 5000,
+            seed: 1,
+        })
+    );
+    assert_eq!(
+        parse_args(args(&["--batch", "views.toml", "-j", "4", "-i", "500"])),
+        Ok(Command::Batch {
+            file: "views.toml".to_string(),
+            jobs: 4,
+            defaults: Options {
+                limit: 500,
+                threads: (num_cpus::get() / 4).max(1),
+                ..Options::default()
+            },
+        })
+    );
+}
+
+#[test]
+fn test_parse_args_errors() {
+    assert_eq!(
+        parse_args(args(&["--size", "400y300"])),
+        Err(CliError::BadPair {
+            flag: "--size".to_string(),
+            value: "400y300".to_string(),
+            error: ParseError::MissingSeparator('x'),
+        })
+    );
+    assert_eq!(
+        parse_args(args(&["--colour", "red"])),
+        Err(CliError::UnknownFlag("--colour".to_string()))
+    );
+    assert_eq!(
+        parse_args(args(&["-o"])),
+        Err(CliError::MissingValue("-o".to_string()))
+    );
+    assert_eq!(
+        parse_args(args(&["mandel.png"])),
+        Err(CliError::UnexpectedArgument("mandel.png".to_string()))
+    );
+    assert!(matches!(
+        parse_args(args(&["-t", "0"])),
+        Err(CliError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        parse_args(args(&["-u", "1,1", "-l", "-1,-1"])),
+        Err(CliError::OutOfRange { .. })
+    ));
+    for size in ["100000x100000", "18446744073709551615x2"] {
+        assert!(matches!(
+            parse_args(args(&["-s", size])),
+            Err(CliError::OutOfRange { .. })
+        ));
+    }
+    assert!(parse_args(args(&["-s", "16384x16384"])).is_ok());
+    assert_eq!(
+        parse_args(args(&["--batch", "jobs.txt", "--analyze"])),
+        Err(CliError::Conflict("--analyze", "--batch"))
+    );
+}
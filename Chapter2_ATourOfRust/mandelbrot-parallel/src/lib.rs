@@ -0,0 +1,357 @@
+pub mod autolimit;
+pub mod batch;
+pub mod cache;
+pub mod cli;
+pub mod mesh;
+pub mod newton;
+pub mod palette;
+pub mod stats;
+
+use cache::Cache;
+use cli::Options;
+use mesh::MeshFormat;
+use num::Complex;
+use std::str::FromStr;
+use image::ColorType;
+use image::png::PngEncoder;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Write the buffer `pixels` whose dimensions are given by `bounds` to the
+/// file named `filename`. `color_type` says how many bytes make up a pixel.
+pub fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize), color_type: ColorType) -> Result<(), std::io::Error>{
+    let output = File::create(filename)?;
+
+    let encoder = PngEncoder::new(output);
+    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, color_type).map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
+/// iterations to decide.
+///
+/// If `c` is not a member, return `Some(i)`, where `i` is the number of
+/// iterations it took for `c` to leave the circle of radius 2 centered on
+/// the origin. If `c` seems to be a member (more precisely, if we reached the
+/// iteration limit without being able to prove that `c` is not a member),
+/// return `None`.
+pub fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    for i in 0..limit {
+        if z.norm_sqr() > 4.0 {
+            return Some(i);
+        }
+        z = z * z + c;
+    }
+    None
+}
+
+/// The ways `parse_pair` can fail.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// The separator character does not appear in the string.
+    MissingSeparator(char),
+    /// The text before the separator is not a valid value.
+    BadLeft(String),
+    /// The text after the separator is not a valid value.
+    BadRight(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingSeparator(sep) => {
+                write!(f, "expected two values separated by '{}'", sep)
+            }
+            ParseError::BadLeft(text) => write!(f, "'{}' before the separator is not a number", text),
+            ParseError::BadRight(text) => write!(f, "'{}' after the separator is not a number", text),
+        }
+    }
+}
+
+/// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0, 0.5"`.
+///
+/// Specifically `s` should have the form <left><sep><right>, where <sep> is
+/// the character given by the `separator` argument, and <left> and <right> are
+/// both strings that can be parsed by `T::from_str`. `separator` must be an
+/// ASCII character.
+///
+/// If `s` has the proper form, return `Ok((x,y))`. If it doesn't parse
+/// correctly, return a `ParseError` saying which part was wrong.
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Result<(T, T), ParseError> {
+    match s.find(separator) {
+        None => Err(ParseError::MissingSeparator(separator)),
+        Some(index) => {
+            let (left, right) = (&s[..index], &s[index + 1..]);
+            match (T::from_str(left), T::from_str(right)) {
+                (Ok(l), Ok(r)) => Ok((l, r)),
+                (Err(_), _) => Err(ParseError::BadLeft(left.to_string())),
+                (_, Err(_)) => Err(ParseError::BadRight(right.to_string())),
+            }
+        }
+    }
+}
+
+/// Parse a pair of floating point numbers separated by a comma as a complex
+/// number.
+pub fn parse_complex(s: &str) -> Result<Complex<f64>, ParseError> {
+    parse_pair(s, ',').map(|(re, im)| Complex { re, im })
+}
+
+/// Given the row and column of a pixel in the output image, return the
+/// corresponding point on the complex plane.
+///
+/// `bounds` is a pair giving the width and height of the image in pixels.
+/// `pixel` is a (column, row) pair indicating a particular pixel in that image.
+///  The `upper_left` and `lower_right` parameters are points on the complex
+/// plane designating the area our image covers.
+pub fn pixel_to_point(
+    bounds: (usize, usize),
+    pixel: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> Complex<f64> {
+    let (width, height) = (
+        lower_right.re - upper_left.re,
+        upper_left.im - lower_right.im,
+    );
+    Complex {
+        re: (upper_left.re + pixel.0 as f64 * width / bounds.0 as f64),
+        im: (upper_left.im - pixel.1 as f64 * height / bounds.1 as f64), // Why subtraction here? pixel.1 increases as we go down,
+                                                                         // but the imaginary component increases as we go up.
+    }
+}
+
+/// Render a rectangle of the Mandelbrot set into a buffer of escape counts.
+///
+/// The `bounds` argument gives the width and height of the buffer `counts`,
+/// which holds one escape count per pixel. The `upper_left` and `lower_right`
+/// arguments specify points on the complex plane corresponding to the upper left
+/// and lower right corners of the pixel buffer. Points that are still inside
+/// the circle after `limit` iterations get a count of `limit`.
+pub fn render(
+    counts: &mut [u32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    limit: u32,
+) {
+    assert!(counts.len() == bounds.0 * bounds.1);
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            counts[row * bounds.0 + column] =
+            match escape_time(point, limit as usize) {
+                None => limit,
+                Some(count) => count as u32
+            };
+        }
+    }
+}
+
+/// Like `render`, but split the buffer into horizontal bands and render each
+/// band on its own thread.
+///
+/// Return how long each band took to render, from top to bottom.
+pub fn render_parallel(
+    counts: &mut [u32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    limit: u32,
+    threads: usize,
+) -> Vec<Duration> {
+    in_bands(counts, bounds, upper_left, lower_right, threads, |band, band_bounds, band_upper_left, band_lower_right| {
+        render(band, band_bounds, band_upper_left, band_lower_right, limit)
+    })
+}
+
+/// Split `buffer`, which holds one value per pixel of an image with the
+/// given `bounds`, into `threads` horizontal bands, and call `render_band` on
+/// each band on its own thread. `render_band` receives the band, its bounds,
+/// and the points on the complex plane at its corners.
+///
+/// Return how long each band took, from top to bottom.
+pub fn in_bands<T, F>(
+    buffer: &mut [T],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    threads: usize,
+    render_band: F,
+) -> Vec<Duration>
+where
+    T: Send,
+    F: Fn(&mut [T], (usize, usize), Complex<f64>, Complex<f64>) + Sync,
+{
+    let rows_per_band = bounds.1 / threads + 1;
+    let render_band = &render_band;
+
+    let bands: Vec<&mut [T]> = buffer.chunks_mut(rows_per_band * bounds.0).collect();
+    crossbeam::scope(|spawner|{
+        let mut handles = Vec::with_capacity(bands.len());
+        for (i, band) in bands.into_iter().enumerate() {
+            let top = rows_per_band * i;
+            let height = band.len() / bounds.0;
+            let band_bounds = (bounds.0, height);
+            let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
+            let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
+            // The move keyword below indicates that the closure takes ownership of the variables it uses.
+            handles.push(spawner.spawn(move |_|{ // The unused param _ is another spawner for making nested threads
+                let start = Instant::now();
+                render_band(band, band_bounds, band_upper_left, band_lower_right);
+                start.elapsed()
+            }));
+        }
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    }).unwrap()
+    // crossbeam::scope call ensures that all threads have completed before it returns
+}
+
+/// A function that fills a buffer of escape counts the way `render` does.
+pub type Kernel = fn(&mut [u32], (usize, usize), Complex<f64>, Complex<f64>, u32);
+
+/// Every way we have of rendering, by name. The golden-image tests check
+/// each of these against the reference renders, and the benchmarks race them,
+/// so a new kernel only needs to be added here.
+pub const KERNELS: &[(&str, Kernel)] = &[("serial", render), ("parallel", render_on_all_cpus)];
+
+/// `render_parallel` with one band per CPU.
+fn render_on_all_cpus(
+    counts: &mut [u32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    limit: u32,
+) {
+    render_parallel(counts, bounds, upper_left, lower_right, limit, num_cpus::get());
+}
+
+/// Render the image or mesh described by `options` and write it to
+/// `options.output`.
+///
+/// Any automatic iteration limit must already have been chosen by
+/// `autolimit::resolve`.
+///
+/// If `options` names a cache directory, reuse whatever we can from it, and
+/// save what we render there. Problems with the cache are reported, but
+/// don't stop us writing the output.
+pub fn render_to_file(options: &Options) -> Result<(), io::Error> {
+    debug_assert!(options.auto_limit.is_none(), "iteration limit not resolved");
+    let cache = match &options.cache_dir {
+        Some(dir) => match Cache::open(dir, options.cache_size) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("warning: not using cache '{}': {}", dir, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let output_name = cache::output_name(options);
+    if let Some(entry) = cache.as_ref().and_then(|cache| cache.lookup(&output_name))
+        && fs::copy(&entry, &options.output).is_ok()
+    {
+        return Ok(());
+    }
+
+    if let Some(polynomial) = &options.newton {
+        render_newton_to_file(options, polynomial)?;
+    } else {
+        render_mandelbrot_to_file(options, cache.as_ref())?;
+    }
+
+    if let Some(cache) = &cache
+        && let Err(e) = fs::read(&options.output).and_then(|data| cache.insert(&output_name, &data))
+    {
+        eprintln!("warning: failed to cache '{}': {}", options.output, e);
+    }
+    Ok(())
+}
+
+fn render_mandelbrot_to_file(options: &Options, cache: Option<&Cache>) -> Result<(), io::Error> {
+    let bounds = options.bounds;
+    let counts_name = cache::counts_name(options);
+    let cached_counts = cache
+        .and_then(|cache| cache.lookup(&counts_name))
+        .and_then(|entry| fs::read(entry).ok())
+        .and_then(|bytes| cache::decode_counts(&bytes, bounds.0 * bounds.1));
+    let counts = match cached_counts {
+        Some(counts) => counts,
+        None => {
+            let mut counts = vec![0; bounds.0 * bounds.1];
+            render_parallel(&mut counts, bounds, options.upper_left, options.lower_right, options.limit, options.threads);
+            if let Some(cache) = cache
+                && let Err(e) = cache.insert(&counts_name, &cache::encode_counts(&counts))
+            {
+                eprintln!("warning: failed to cache escape counts: {}", e);
+            }
+            counts
+        }
+    };
+
+    match MeshFormat::from_filename(&options.output) {
+        Some(format) => {
+            let mesh = mesh::heightmap(&counts, bounds, options.limit, options.height_scale, options.smoothing);
+            mesh::write_mesh(&options.output, &mesh, format)
+        }
+        None => {
+            let pixels = options.palette.colorize(&counts, options.limit);
+            write_image(&options.output, &pixels, bounds, options.palette.color_type())
+        }
+    }
+}
+
+fn render_newton_to_file(options: &Options, polynomial: &newton::Polynomial) -> Result<(), io::Error> {
+    let bounds = options.bounds;
+    let roots = polynomial.roots();
+    let mut basins = vec![None; bounds.0 * bounds.1];
+    in_bands(&mut basins, bounds, options.upper_left, options.lower_right, options.threads, |band, band_bounds, band_upper_left, band_lower_right| {
+        newton::render(band, band_bounds, band_upper_left, band_lower_right, polynomial, &roots, options.limit)
+    });
+
+    let pixels = newton::colorize(&basins, roots.len());
+    write_image(&options.output, &pixels, bounds, ColorType::Rgb8)
+}
+
+#[test]
+fn test_parse_pair() {
+    assert_eq!(parse_pair::<i32>("", ','), Err(ParseError::MissingSeparator(',')));
+    assert_eq!(parse_pair::<i32>("10,", ','), Err(ParseError::BadRight("".to_string())));
+    assert_eq!(parse_pair::<i32>(",10", ','), Err(ParseError::BadLeft("".to_string())));
+    assert_eq!(parse_pair::<i32>("10,20", ','), Ok((10, 20)));
+    assert_eq!(parse_pair::<i32>("10,20xy", ','), Err(ParseError::BadRight("20xy".to_string())));
+    assert_eq!(parse_pair::<f64>("0.5x", 'x'), Err(ParseError::BadRight("".to_string())));
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Ok((0.5, 1.5)));
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(
+        parse_complex("1.25,-0.0625"),
+        Ok(Complex {
+            re: 1.25,
+            im: -0.0625
+        })
+    );
+    assert_eq!(parse_complex(", 0.0625"), Err(ParseError::BadLeft("".to_string())));
+}
+
+#[test]
+fn test_pixel_to_point() {
+    assert_eq!(
+        pixel_to_point(
+            (100, 200),
+            (25, 175),
+            Complex { re: -1.0, im: 1.0 },
+            Complex { re: 1.0, im: -1.0 }
+        ),
+        Complex {
+            re: -0.5,
+            im: -0.75
+        }
+    );
+}
@@ -1,197 +1,60 @@
-use num::Complex;
-use std::str::FromStr;
-use image::ColorType;
-use image::png::PngEncoder;
-use std::fs::File;
-use std::io;
+use mandelbrot::autolimit;
+use mandelbrot::batch;
+use mandelbrot::cli::{self, Command};
+use mandelbrot::render_to_file;
+use mandelbrot::stats;
 use std::env;
-use num_cpus;
 
 // To build: cargo build --release
-// To perform an integration test:  time target/release/mandelbrot mandel.png 4000x3000 -1.20,0.35 -1,0.20
+// To perform an integration test:  time target/release/mandelbrot -o mandel.png -s 4000x3000 -u -1.20,0.35 -l -1,0.20
+// To see all the options:  target/release/mandelbrot --help
+// To run the benchmarks:  cargo bench
 
-/// Write the buffer `pixels` whose dimensions are given by `bounds` to the
-/// file named `filename`.
-fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), std::io::Error>{
-    let output = File::create(filename)?;
-
-    let encoder = PngEncoder::new(output);
-    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, ColorType::L8).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-    Ok(())
-}
-
-/// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
-/// iterations to decide.
-///
-/// If `c` is not a member, return `Some(i)`, where `i` is the number of
-/// iterations it took for `c` to leave the circle of radius 2 centered on
-/// the origin. If `c` seems to be a member (more precisely, if we reached the
-/// iteration limit without being able to prove that `c` is not a member),
-/// return `None`.
-
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
+fn main() {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "mandelbrot".to_string());
+
+    let command = match cli::parse_args(args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try '{} --help' for more information.", program);
+            std::process::exit(2);
         }
-        z = z * z + c;
-    }
-    None
-}
+    };
 
-/// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0, 0.5"`.
-///
-/// Specifically `s` should have the form <left><sep><right>, where <sep> is
-/// the character given by the `separator` argument, and <left> and <right> are
-/// both strings that can be parsed by `T::from_str`. `separator` must be an
-/// ASCII character.
-///
-/// If `s` has the proper form, return `Some<(x,y)>`. If it doesn't parse
-/// correctly, return `None`
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
-    match s.find(separator) {
-        None => None,
-        Some(index) => match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-            (Ok(l), Ok(r)) => Some((l, r)),
-            _ => None,
-        },
-    }
-}
-
-/// Parse a pair of floating point numbers separated by a comma as a complex
-/// number.
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair(s, ',') {
-        Some((re, im)) => Some(Complex { re, im }),
-        None => None,
-    }
-}
-
-/// Given the row and column of a pixel in the output image, return the
-/// corresponding point on the complex plane.
-///
-/// `bounds` is a pair giving the width and height of the image in pixels.
-/// `pixel` is a (column, row) pair indicating a particular pixel in that image.
-///  The `upper_left` and `lower_right` parameters are points on the complex
-/// plane designating the area our image covers.
-fn pixel_to_point(
-    bounds: (usize, usize),
-    pixel: (usize, usize),
-    upper_left: Complex<f64>,
-    lower_right: Complex<f64>,
-) -> Complex<f64> {
-    let (width, height) = (
-        lower_right.re - upper_left.re,
-        upper_left.im - lower_right.im,
-    );
-    Complex {
-        re: (upper_left.re + pixel.0 as f64 * width / bounds.0 as f64),
-        im: (upper_left.im - pixel.1 as f64 * height / bounds.1 as f64), // Why subtraction here? pixel.1 increases as we go down,
-                                                                         // but the imaginary component increases as we go up.
-    }
-}
-
-/// Render a rectangle of the Mandelbrot set into a buffer of pixels.
-///
-/// The `bounds` argument gives the width and height of the buffer `pixels`,
-/// which holds one grayscale pixel per byte. The `upper_left` and `lower_right`
-/// arguments specify points on the complex plane corresponding to the upper lef
-/// and lower right corners of the pixel buffer.
-fn render(
-    pixels: &mut [u8],
-    bounds: (usize, usize),
-    upper_left: Complex<f64>,
-    lower_right: Complex<f64>,
-) {
-    assert!(pixels.len() == bounds.0 * bounds.1);
-    for row in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
-            pixels[row * bounds.0 + column] =
-            match escape_time(point, 255) {
-                None => 0,
-                Some(count) => 255 - count as u8
-            };
+    match command {
+        Command::Help => cli::print_usage(&program),
+        Command::Render(mut options) => {
+            if let Some(limit) = autolimit::resolve(&mut options) {
+                println!("chose an iteration limit of {}", limit);
+            }
+            println!("rendering on {} threads", options.threads);
+            if let Err(e) = render_to_file(&options) {
+                eprintln!("error: failed to write '{}': {}", options.output, e);
+                std::process::exit(1);
+            }
         }
-    }
-}
-
-
-fn main() {
-    let  args: Vec<String> = env::args().collect();
-    if args.len() != 5 {
-        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT", args[0]);
-        eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20", args[0]);
-        std::process::exit(1);
-    }
-
-    let bounds = parse_pair::<usize>(&args[2], 'x').expect("error parsing image dimensions");
-    let upper_left = parse_complex(&args[3]).expect("error parsing upper left corner point");
-    let lower_right = parse_complex(&args[4]).expect("error parsing lower right corner point");
-
-    let mut pixels = vec![0; bounds.0 * bounds.1];
-
-    let threads = num_cpus::get(); // Obtain the number of cores in the system
-    println!("{} cpus detected", threads);
-    let rows_per_band = bounds.1 / threads + 1;
-
-    {
-        let bands: Vec<&mut [u8]> = pixels.chunks_mut(rows_per_band * bounds.0).collect();
-        crossbeam::scope(|spawner|{
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / bounds.0;
-                let band_bounds = (bounds.0, height);
-                let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
-                let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
-                // The move keyword below indicates that the closure takes ownership of the variables it uses.
-                spawner.spawn(move |_|{ // The unused param _ is another spawner for making nested threads
-                    render(band, band_bounds, band_upper_left, band_lower_right);
-                });
+        Command::Analyze { mut options, samples, seed } => {
+            if let Some(limit) = autolimit::resolve(&mut options) {
+                eprintln!("chose an iteration limit of {}", limit);
             }
-        }).unwrap();
-        // crossbeam::scope call ensures that all threads have completed before it returns
-    }
-
-    write_image(&args[1], &pixels, bounds).expect("error writing PNG file");
-}
-
-#[test]
-fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("", ','), None);
-    assert_eq!(parse_pair::<i32>("10,", ','), None);
-    assert_eq!(parse_pair::<i32>(",10", ','), None);
-    assert_eq!(parse_pair::<i32>("10,20", ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x", 'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
-}
-
-#[test]
-fn test_parse_complex() {
-    assert_eq!(
-        parse_complex("1.25,-0.0625"),
-        Some(Complex {
-            re: 1.25,
-            im: -0.0625
-        })
-    );
-    assert_eq!(parse_complex(", 0.0625"), None);
-}
-
-#[test]
-fn test_pixel_to_point() {
-    assert_eq!(
-        pixel_to_point(
-            (100, 200),
-            (25, 175),
-            Complex { re: -1.0, im: 1.0 },
-            Complex { re: 1.0, im: -1.0 }
-        ),
-        Complex {
-            re: -0.5,
-            im: -0.75
+            let stats = stats::analyze(&options, samples, seed);
+            println!("{}", serde_json::to_string_pretty(&stats).unwrap());
         }
-    );
+        Command::Batch { file, jobs, defaults } => {
+            let list = match batch::load_job_file(&file, &defaults) {
+                Ok(list) => list,
+                Err(e) => {
+                    eprintln!("error: {}: {}", file, e);
+                    std::process::exit(1);
+                }
+            };
+            let report = batch::run(list, jobs);
+            report.print();
+            if report.failures() > 0 {
+                std::process::exit(1);
+            }
+        }
+    }
 }
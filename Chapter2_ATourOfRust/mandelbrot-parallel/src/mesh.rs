@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// A triangle mesh: a list of points, and triangles given as indices into it.
+///
+/// Triangles are wound counter-clockwise when seen from outside the solid.
+#[derive(Debug)]
+pub struct Mesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// The mesh file formats we can write.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeshFormat {
+    Obj,
+    Stl,
+    Gltf,
+}
+
+impl MeshFormat {
+    /// Choose a format from the extension of `filename`, or return `None` if
+    /// it isn't a mesh file.
+    pub fn from_filename(filename: &str) -> Option<MeshFormat> {
+        let extension = Path::new(filename).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => Some(MeshFormat::Obj),
+            "stl" => Some(MeshFormat::Stl),
+            "gltf" => Some(MeshFormat::Gltf),
+            _ => None,
+        }
+    }
+}
+
+/// How thick the slab under the lowest point of the surface is.
+const BASE_THICKNESS: f32 = 1.0;
+
+/// Build a closed solid whose top surface is a heightmap of `counts`.
+///
+/// `counts` and `bounds` are as produced by `render`. Each pixel becomes one
+/// vertex, one unit apart in x and y, with the image's top row at the far
+/// edge. A pixel's height is its escape count as a fraction of `limit`, times
+/// `height_scale`, so points in the set form a plateau `height_scale` high.
+/// The height field is blurred with `smoothing` passes of a 3x3 box filter
+/// first. The sides and bottom are closed off so the result can be printed.
+pub fn heightmap(
+    counts: &[u32],
+    bounds: (usize, usize),
+    limit: u32,
+    height_scale: f32,
+    smoothing: usize,
+) -> Mesh {
+    let (width, height) = bounds;
+    assert!(counts.len() == width * height);
+    assert!(width >= 2 && height >= 2, "a heightmap needs at least 2x2 pixels");
+
+    let mut heights: Vec<f32> = counts
+        .iter()
+        .map(|&count| count.min(limit) as f32 / limit as f32)
+        .collect();
+    for _ in 0..smoothing {
+        heights = box_blur(&heights, bounds);
+    }
+
+    let mut vertices = Vec::with_capacity(width * height + 2 * (width + height));
+    for row in 0..height {
+        for column in 0..width {
+            vertices.push([
+                column as f32,
+                (height - 1 - row) as f32,
+                heights[row * width + column] * height_scale,
+            ]);
+        }
+    }
+    let top = |column: usize, row: usize| (row * width + column) as u32;
+
+    let mut triangles = Vec::with_capacity(2 * (width - 1) * (height - 1) + 6 * (width + height));
+    for row in 0..height - 1 {
+        for column in 0..width - 1 {
+            let upper_left = top(column, row);
+            let upper_right = top(column + 1, row);
+            let lower_left = top(column, row + 1);
+            let lower_right = top(column + 1, row + 1);
+            triangles.push([lower_left, lower_right, upper_right]);
+            triangles.push([lower_left, upper_right, upper_left]);
+        }
+    }
+
+    // Walk the edge of the grid counter-clockwise as seen from above, and
+    // drop a wall from each edge segment down to the base.
+    let mut rim = Vec::with_capacity(2 * (width + height));
+    rim.extend((0..width).map(|column| top(column, height - 1)));
+    rim.extend((0..height - 1).rev().map(|row| top(width - 1, row)));
+    rim.extend((0..width - 1).rev().map(|column| top(column, 0)));
+    rim.extend((1..height - 1).map(|row| top(0, row)));
+
+    let first_bottom = vertices.len() as u32;
+    for &index in &rim {
+        let [x, y, _] = vertices[index as usize];
+        vertices.push([x, y, -BASE_THICKNESS]);
+    }
+    let center = vertices.len() as u32;
+    vertices.push([
+        (width - 1) as f32 / 2.0,
+        (height - 1) as f32 / 2.0,
+        -BASE_THICKNESS,
+    ]);
+
+    for i in 0..rim.len() {
+        let next = (i + 1) % rim.len();
+        let (a, b) = (rim[i], rim[next]);
+        let (a_bottom, b_bottom) = (first_bottom + i as u32, first_bottom + next as u32);
+        triangles.push([a_bottom, b_bottom, b]);
+        triangles.push([a_bottom, b, a]);
+        // The bottom faces down, so it winds clockwise seen from above.
+        triangles.push([center, b_bottom, a_bottom]);
+    }
+
+    Mesh {
+        vertices,
+        triangles,
+    }
+}
+
+/// Replace each height with the average of its 3x3 neighborhood, clamped at
+/// the edges of the grid.
+fn box_blur(heights: &[f32], bounds: (usize, usize)) -> Vec<f32> {
+    let (width, height) = bounds;
+    let mut blurred = Vec::with_capacity(heights.len());
+    for row in 0..height {
+        for column in 0..width {
+            let mut sum = 0.0;
+            let mut n = 0;
+            for r in row.saturating_sub(1)..(row + 2).min(height) {
+                for c in column.saturating_sub(1)..(column + 2).min(width) {
+                    sum += heights[r * width + c];
+                    n += 1;
+                }
+            }
+            blurred.push(sum / n as f32);
+        }
+    }
+    blurred
+}
+
+/// Write `mesh` to the file named `filename` in the given format.
+pub fn write_mesh(filename: &str, mesh: &Mesh, format: MeshFormat) -> Result<(), io::Error> {
+    let mut output = BufWriter::new(File::create(filename)?);
+    match format {
+        MeshFormat::Obj => write_obj(&mut output, mesh)?,
+        MeshFormat::Stl => write_stl(&mut output, mesh)?,
+        MeshFormat::Gltf => write_gltf(&mut output, mesh)?,
+    }
+    output.flush()
+}
+
+/// Write `mesh` as Wavefront OBJ text.
+fn write_obj<W: Write>(out: &mut W, mesh: &Mesh) -> io::Result<()> {
+    writeln!(out, "# Mandelbrot heightmap")?;
+    for [x, y, z] in &mesh.vertices {
+        writeln!(out, "v {} {} {}", x, y, z)?;
+    }
+    // OBJ indices start at 1.
+    for [a, b, c] in &mesh.triangles {
+        writeln!(out, "f {} {} {}", a + 1, b + 1, c + 1)?;
+    }
+    Ok(())
+}
+
+/// Write `mesh` as binary STL.
+fn write_stl<W: Write>(out: &mut W, mesh: &Mesh) -> io::Result<()> {
+    let mut header = [0u8; 80];
+    let title = b"Mandelbrot heightmap";
+    header[..title.len()].copy_from_slice(title);
+    out.write_all(&header)?;
+    out.write_all(&(mesh.triangles.len() as u32).to_le_bytes())?;
+
+    for triangle in &mesh.triangles {
+        let [a, b, c] = triangle.map(|i| mesh.vertices[i as usize]);
+        for component in normal(a, b, c).iter().chain(&a).chain(&b).chain(&c) {
+            out.write_all(&component.to_le_bytes())?;
+        }
+        // The "attribute byte count", which nobody uses.
+        out.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+/// The unit normal of the triangle `a`, `b`, `c`, wound counter-clockwise.
+fn normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length == 0.0 {
+        [0.0, 0.0, 0.0]
+    } else {
+        [n[0] / length, n[1] / length, n[2] / length]
+    }
+}
+
+/// Write `mesh` as a glTF 2.0 file, with its buffer embedded as a data URI so
+/// the result is a single self-contained file.
+fn write_gltf<W: Write>(out: &mut W, mesh: &Mesh) -> io::Result<()> {
+    let mut buffer = Vec::with_capacity(mesh.vertices.len() * 12 + mesh.triangles.len() * 12);
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for vertex in &mesh.vertices {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(vertex[axis]);
+            max[axis] = max[axis].max(vertex[axis]);
+            buffer.extend_from_slice(&vertex[axis].to_le_bytes());
+        }
+    }
+    let positions_length = buffer.len();
+    for index in mesh.triangles.iter().flatten() {
+        buffer.extend_from_slice(&index.to_le_bytes());
+    }
+    let indices_length = buffer.len() - positions_length;
+
+    // glTF is y-up, while our heightmap is z-up, so tip it over with a
+    // rotation of -90 degrees about the x axis.
+    let half = std::f32::consts::FRAC_1_SQRT_2;
+    write!(
+        out,
+        r#"{{
+  "asset": {{ "version": "2.0", "generator": "mandelbrot" }},
+  "scene": 0,
+  "scenes": [ {{ "nodes": [0] }} ],
+  "nodes": [ {{ "mesh": 0, "rotation": [{}, 0, 0, {}] }} ],
+  "meshes": [ {{ "primitives": [ {{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }} ] }} ],
+  "accessors": [
+    {{ "bufferView": 0, "componentType": 5126, "count": {}, "type": "VEC3",
+       "min": [{}, {}, {}], "max": [{}, {}, {}] }},
+    {{ "bufferView": 1, "componentType": 5125, "count": {}, "type": "SCALAR" }}
+  ],
+  "bufferViews": [
+    {{ "buffer": 0, "byteOffset": 0, "byteLength": {}, "target": 34962 }},
+    {{ "buffer": 0, "byteOffset": {}, "byteLength": {}, "target": 34963 }}
+  ],
+  "buffers": [ {{ "byteLength": {}, "uri": "data:application/octet-stream;base64,"#,
+        -half,
+        half,
+        mesh.vertices.len(),
+        min[0],
+        min[1],
+        min[2],
+        max[0],
+        max[1],
+        max[2],
+        mesh.triangles.len() * 3,
+        positions_length,
+        positions_length,
+        indices_length,
+        buffer.len(),
+    )?;
+    write_base64(out, &buffer)?;
+    writeln!(out, "\" }} ]\n}}")
+}
+
+/// Write `data` to `out` in standard base64 with padding.
+fn write_base64<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        let mut encoded = [b'='; 4];
+        for (i, digit) in encoded.iter_mut().enumerate().take(chunk.len() + 1) {
+            *digit = ALPHABET[(bits >> (18 - 6 * i) & 63) as usize];
+        }
+        out.write_all(&encoded)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_heightmap_is_closed() {
+    use std::collections::HashMap;
+
+    let counts = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+    let mesh = heightmap(&counts, (4, 3), 10, 5.0, 1);
+    assert_eq!(mesh.vertices.len(), 12 + 10 + 1);
+    assert_eq!(mesh.triangles.len(), 2 * 3 * 2 + 3 * 10);
+
+    // In a closed, consistently wound mesh every directed edge appears once,
+    // and its reverse belongs to exactly one other triangle.
+    let mut edges = HashMap::new();
+    for &[a, b, c] in &mesh.triangles {
+        for edge in [(a, b), (b, c), (c, a)] {
+            *edges.entry(edge).or_insert(0) += 1;
+        }
+    }
+    for (&(a, b), &n) in &edges {
+        assert_eq!(n, 1);
+        assert_eq!(edges.get(&(b, a)), Some(&1));
+    }
+}
+
+#[test]
+fn test_base64() {
+    let mut out = Vec::new();
+    write_base64(&mut out, b"Mandelbrot").unwrap();
+    assert_eq!(out, b"TWFuZGVsYnJvdA==");
+}
@@ -0,0 +1,253 @@
+use crate::pixel_to_point;
+use num::Complex;
+use std::fmt;
+use std::str::FromStr;
+
+/// A polynomial with real coefficients, for drawing Newton fractals.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    /// The coefficients, highest power first. The first is never zero, and
+    /// there are always at least two.
+    coefficients: Vec<f64>,
+}
+
+/// Why a list of coefficients isn't a polynomial we can draw.
+#[derive(Debug, PartialEq)]
+pub enum PolynomialError {
+    BadCoefficient(String),
+    /// Newton's method needs something to solve: the polynomial must have
+    /// degree one or more.
+    Constant,
+}
+
+impl fmt::Display for PolynomialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolynomialError::BadCoefficient(text) => {
+                write!(f, "'{}' is not a number", text)
+            }
+            PolynomialError::Constant => {
+                f.write_str("expected a polynomial of degree one or more")
+            }
+        }
+    }
+}
+
+impl FromStr for Polynomial {
+    type Err = PolynomialError;
+
+    /// Parse a comma-separated list of coefficients, highest power first:
+    /// `"1,0,0,-1"` is z³ - 1.
+    fn from_str(s: &str) -> Result<Polynomial, PolynomialError> {
+        let mut coefficients = s
+            .split(',')
+            .map(|text| {
+                f64::from_str(text.trim())
+                    .ok()
+                    .filter(|c| c.is_finite())
+                    .ok_or_else(|| PolynomialError::BadCoefficient(text.to_string()))
+            })
+            .collect::<Result<Vec<f64>, _>>()?;
+
+        let leading_zeros = coefficients.iter().take_while(|&&c| c == 0.0).count();
+        coefficients.drain(..leading_zeros);
+        if coefficients.len() < 2 {
+            return Err(PolynomialError::Constant);
+        }
+        Ok(Polynomial { coefficients })
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let texts: Vec<String> = self.coefficients.iter().map(|c| c.to_string()).collect();
+        f.write_str(&texts.join(","))
+    }
+}
+
+impl Polynomial {
+    /// Return the value of the polynomial and of its derivative at `z`.
+    fn evaluate(&self, z: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        // Horner's rule, differentiating as we go.
+        let mut value = Complex { re: 0.0, im: 0.0 };
+        let mut derivative = Complex { re: 0.0, im: 0.0 };
+        for &c in &self.coefficients {
+            derivative = derivative * z + value;
+            value = value * z + c;
+        }
+        (value, derivative)
+    }
+
+    /// Find all the distinct roots, using the Durand-Kerner method.
+    pub fn roots(&self) -> Vec<Complex<f64>> {
+        let degree = self.coefficients.len() - 1;
+        let lead = self.coefficients[0];
+        let monic = Polynomial {
+            coefficients: self.coefficients.iter().map(|c| c / lead).collect(),
+        };
+
+        // The usual starting points: powers of a number that is neither
+        // real nor a root of unity.
+        let seed = Complex { re: 0.4, im: 0.9 };
+        let mut roots: Vec<Complex<f64>> = (0..degree).map(|i| seed.powu(i as u32)).collect();
+        for _ in 0..1000 {
+            let mut largest_step: f64 = 0.0;
+            for i in 0..degree {
+                let mut denominator = Complex { re: 1.0, im: 0.0 };
+                for j in 0..degree {
+                    if i != j {
+                        denominator *= roots[i] - roots[j];
+                    }
+                }
+                let step = monic.evaluate(roots[i]).0 / denominator;
+                if step.is_finite() {
+                    roots[i] -= step;
+                    largest_step = largest_step.max(step.norm());
+                }
+            }
+            if largest_step < 1e-14 {
+                break;
+            }
+        }
+
+        // A repeated root shows up several times, never quite agreeing with
+        // itself; keep one copy.
+        let mut distinct: Vec<Complex<f64>> = Vec::with_capacity(degree);
+        for root in roots {
+            if distinct.iter().all(|d| (d - root).norm() > 1e-4) {
+                distinct.push(root);
+            }
+        }
+        distinct
+    }
+}
+
+/// Where Newton's method took one starting point: the index of the root it
+/// reached and how many steps that took, or `None` if it didn't arrive
+/// within the iteration limit.
+pub type Basin = Option<(u16, u32)>;
+
+/// Once Newton's method is taking steps smaller than this, we consider it to
+/// have converged.
+const TOLERANCE: f64 = 1e-9;
+
+/// Run Newton's method on `polynomial` from `z`, for at most `limit` steps,
+/// and report which of its `roots` it converged to.
+pub fn basin(polynomial: &Polynomial, roots: &[Complex<f64>], mut z: Complex<f64>, limit: u32) -> Basin {
+    for i in 0..limit {
+        let (value, derivative) = polynomial.evaluate(z);
+        let step = value / derivative;
+        if !step.is_finite() {
+            // We landed on a critical point, where the tangent is flat.
+            return None;
+        }
+        z -= step;
+        if step.norm_sqr() < TOLERANCE * TOLERANCE {
+            // Near a repeated root, the roots we found and the point we
+            // converged to may differ by more than `TOLERANCE`, so just
+            // pick the closest.
+            let nearest = (0..roots.len())
+                .min_by(|&a, &b| (roots[a] - z).norm_sqr().total_cmp(&(roots[b] - z).norm_sqr()))?;
+            return Some((nearest as u16, i + 1));
+        }
+    }
+    None
+}
+
+/// Render a rectangle of the Newton fractal for `polynomial` into `basins`.
+///
+/// The arguments are as for `render`, with `roots` being the roots of
+/// `polynomial`.
+pub fn render(
+    basins: &mut [Basin],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    polynomial: &Polynomial,
+    roots: &[Complex<f64>],
+    limit: u32,
+) {
+    assert!(basins.len() == bounds.0 * bounds.1);
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            basins[row * bounds.0 + column] = basin(polynomial, roots, point, limit);
+        }
+    }
+}
+
+/// Color `basins` as RGB pixels: each root gets its own hue, and points are
+/// darker the longer they took to reach it. Points that never arrived are
+/// black.
+pub fn colorize(basins: &[Basin], root_count: usize) -> Vec<u8> {
+    let slowest = basins
+        .iter()
+        .filter_map(|basin| basin.map(|(_, steps)| steps))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut pixels = Vec::with_capacity(basins.len() * 3);
+    for basin in basins {
+        let rgb = match basin {
+            None => [0, 0, 0],
+            Some((root, steps)) => {
+                let hue = *root as f64 / root_count as f64;
+                let value = 1.0 - 0.85 * (*steps as f64 / slowest as f64).sqrt();
+                hsv_to_rgb(hue, 0.75, value)
+            }
+        };
+        pixels.extend_from_slice(&rgb);
+    }
+    pixels
+}
+
+/// Convert a color given as hue, saturation and value, all from 0 to 1.
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let sector = (hue.fract() * 6.0).floor();
+    let f = hue.fract() * 6.0 - sector;
+    let p = value * (1.0 - saturation);
+    let q = value * (1.0 - saturation * f);
+    let t = value * (1.0 - saturation * (1.0 - f));
+    let (r, g, b) = match sector as u32 {
+        0 => (value, t, p),
+        1 => (q, value, p),
+        2 => (p, value, t),
+        3 => (p, q, value),
+        4 => (t, p, value),
+        _ => (value, p, q),
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
+
+#[test]
+fn test_parse_polynomial() {
+    let cubic: Polynomial = "0, 1,0,0,-1".parse().unwrap();
+    assert_eq!(cubic.to_string(), "1,0,0,-1");
+    assert_eq!("3".parse::<Polynomial>(), Err(PolynomialError::Constant));
+    assert_eq!(
+        "1,x".parse::<Polynomial>(),
+        Err(PolynomialError::BadCoefficient("x".to_string()))
+    );
+}
+
+#[test]
+fn test_roots_and_basins() {
+    // z³ - 1 has the three cube roots of unity as its roots.
+    let cubic: Polynomial = "1,0,0,-1".parse().unwrap();
+    let roots = cubic.roots();
+    assert_eq!(roots.len(), 3);
+    for root in &roots {
+        assert!((root.powu(3) - 1.0).norm() < 1e-9);
+    }
+
+    // Points near a root fall straight into it.
+    let one = roots.iter().position(|r| (r - 1.0).norm() < 1e-9).unwrap();
+    assert_eq!(basin(&cubic, &roots, Complex { re: 1.1, im: 0.0 }, 50).map(|b| b.0), Some(one as u16));
+    // The origin is a critical point, where Newton's method breaks down.
+    assert_eq!(basin(&cubic, &roots, Complex { re: 0.0, im: 0.0 }, 50), None);
+
+    // A repeated root is only reported once.
+    let square: Polynomial = "1,-2,1".parse().unwrap();
+    assert_eq!(square.roots().len(), 1);
+}
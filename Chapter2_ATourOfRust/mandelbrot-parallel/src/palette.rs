@@ -0,0 +1,107 @@
+use image::ColorType;
+use std::fmt;
+use std::str::FromStr;
+
+/// The ways we know how to turn escape counts into pixel colors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Palette {
+    /// One byte per pixel: points that escape quickly are light, points in
+    /// the set are black. This is the book's original coloring.
+    Grayscale,
+    /// Black through red and yellow to white.
+    Fire,
+    /// Black through blue and cyan to white.
+    Ocean,
+}
+
+pub const PALETTE_NAMES: &str = "grayscale, fire, ocean";
+
+impl FromStr for Palette {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Palette, ()> {
+        match s {
+            "grayscale" | "gray" => Ok(Palette::Grayscale),
+            "fire" => Ok(Palette::Fire),
+            "ocean" => Ok(Palette::Ocean),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Palette::Grayscale => "grayscale",
+            Palette::Fire => "fire",
+            Palette::Ocean => "ocean",
+        })
+    }
+}
+
+impl Palette {
+    /// The color type of the buffer returned by `colorize`.
+    pub fn color_type(self) -> ColorType {
+        match self {
+            Palette::Grayscale => ColorType::L8,
+            Palette::Fire | Palette::Ocean => ColorType::Rgb8,
+        }
+    }
+
+    /// Turn a grid of escape counts into pixel data for `write_image`.
+    ///
+    /// A count equal to `limit` marks a point that never escaped, which we
+    /// take to be a member of the set and paint black.
+    pub fn colorize(self, counts: &[u32], limit: u32) -> Vec<u8> {
+        match self {
+            Palette::Grayscale => counts
+                .iter()
+                .map(|&count| {
+                    if count >= limit {
+                        0
+                    } else {
+                        255 - scale(count, limit)
+                    }
+                })
+                .collect(),
+            Palette::Fire | Palette::Ocean => {
+                let mut pixels = Vec::with_capacity(counts.len() * 3);
+                for &count in counts {
+                    let [hot, warm, cool] = if count >= limit {
+                        [0, 0, 0]
+                    } else {
+                        ramp(count, limit)
+                    };
+                    if self == Palette::Fire {
+                        pixels.extend_from_slice(&[hot, warm, cool]);
+                    } else {
+                        pixels.extend_from_slice(&[cool, warm, hot]);
+                    }
+                }
+                pixels
+            }
+        }
+    }
+}
+
+/// Map `count`, which is less than `limit`, onto 0..=255.
+fn scale(count: u32, limit: u32) -> u8 {
+    (count as u64 * 255 / limit as u64) as u8
+}
+
+/// A three-channel ramp that fills the first channel, then the second,
+/// then the third as `count` approaches `limit`.
+fn ramp(count: u32, limit: u32) -> [u8; 3] {
+    let t = count as f64 / limit as f64 * 3.0;
+    let channel = |offset: f64| ((t - offset).clamp(0.0, 1.0) * 255.0) as u8;
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
+
+#[test]
+fn test_grayscale_matches_book_coloring() {
+    let counts = [0, 1, 100, 254, 255];
+    assert_eq!(
+        Palette::Grayscale.colorize(&counts, 255),
+        vec![255, 254, 155, 1, 0]
+    );
+}
@@ -0,0 +1,219 @@
+use crate::cli::Options;
+use crate::{escape_time, render_parallel};
+use num::Complex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Statistics about one render of a viewport, for `--analyze`.
+#[derive(Debug, Serialize)]
+pub struct RenderStats {
+    pub width: usize,
+    pub height: usize,
+    pub upper_left: [f64; 2],
+    pub lower_right: [f64; 2],
+    pub limit: u32,
+    /// The fraction of pixels that never escaped, and so are taken to be
+    /// in the set.
+    pub interior_fraction: f64,
+    /// The mean escape count over all pixels, counting interior pixels as
+    /// `limit`: the average work done per pixel.
+    pub mean_iterations: f64,
+    /// Pairs of `[escape count, number of pixels]` for every count that
+    /// occurs among the pixels that escaped.
+    pub histogram: Vec<(u32, u64)>,
+    pub render_seconds: f64,
+    /// How long each band took to render, from the top of the image down.
+    pub band_seconds: Vec<f64>,
+    pub area: AreaEstimate,
+}
+
+/// A Monte-Carlo estimate of the area of the whole Mandelbrot set.
+#[derive(Debug, Serialize)]
+pub struct AreaEstimate {
+    pub samples: u64,
+    pub limit: u32,
+    pub seed: u64,
+    pub estimate: f64,
+    pub standard_error: f64,
+    /// A 95% confidence interval around `estimate`. Since a point that
+    /// hasn't escaped after `limit` iterations counts as a member, the
+    /// estimate is biased upwards; raising `limit` shrinks the bias.
+    pub confidence_95: [f64; 2],
+    pub seconds: f64,
+}
+
+/// Render the viewport described by `options` and gather statistics about
+/// it, along with an estimate of the area of the set from `samples` random
+/// points.
+pub fn analyze(options: &Options, samples: u64, seed: u64) -> RenderStats {
+    let bounds = options.bounds;
+    let mut counts = vec![0; bounds.0 * bounds.1];
+    let start = Instant::now();
+    let band_times = render_parallel(
+        &mut counts,
+        bounds,
+        options.upper_left,
+        options.lower_right,
+        options.limit,
+        options.threads,
+    );
+    let render_seconds = start.elapsed().as_secs_f64();
+
+    // Keyed by the counts that actually occur: a vector indexed by count
+    // would need `limit` entries, however high that is.
+    let mut histogram = BTreeMap::new();
+    let mut interior = 0u64;
+    let mut total_iterations = 0u64;
+    for &count in &counts {
+        total_iterations += count as u64;
+        if count >= options.limit {
+            interior += 1;
+        } else {
+            *histogram.entry(count).or_insert(0u64) += 1;
+        }
+    }
+    let pixels = counts.len() as f64;
+
+    RenderStats {
+        width: bounds.0,
+        height: bounds.1,
+        upper_left: [options.upper_left.re, options.upper_left.im],
+        lower_right: [options.lower_right.re, options.lower_right.im],
+        limit: options.limit,
+        interior_fraction: interior as f64 / pixels,
+        mean_iterations: total_iterations as f64 / pixels,
+        histogram: histogram.into_iter().collect(),
+        render_seconds,
+        band_seconds: band_times.iter().map(|t| t.as_secs_f64()).collect(),
+        area: estimate_area(samples, options.limit, seed, options.threads),
+    }
+}
+
+/// The set lies within this rectangle, and is symmetric about the real axis,
+/// so we only sample the upper half and double the result.
+const SAMPLE_UPPER_LEFT: Complex<f64> = Complex { re: -2.0, im: 1.25 };
+const SAMPLE_LOWER_RIGHT: Complex<f64> = Complex { re: 0.5, im: 0.0 };
+
+/// How many samples come from each stream of random numbers. Streams are
+/// numbered from the start of the samples, not by thread, and threads take
+/// whole streams.
+const SAMPLES_PER_STREAM: u64 = 1 << 16;
+
+/// Estimate the area of the Mandelbrot set by testing `samples` uniformly
+/// random points with `escape_time`, spread across `threads` threads.
+///
+/// The result depends only on `samples`, `limit` and `seed`, not on how
+/// many threads there are, so runs can be repeated exactly on any machine.
+pub fn estimate_area(samples: u64, limit: u32, seed: u64, threads: usize) -> AreaEstimate {
+    let start = Instant::now();
+    let streams = samples.div_ceil(SAMPLES_PER_STREAM);
+    let threads = (threads as u64).clamp(1, streams.max(1));
+
+    let hits: u64 = crossbeam::scope(|spawner| {
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                spawner.spawn(move |_| {
+                    (i..streams)
+                        .step_by(threads as usize)
+                        .map(|stream| {
+                            let mut rng = SplitMix64(seed ^ stream.wrapping_mul(0x9e3779b97f4a7c15));
+                            let share = SAMPLES_PER_STREAM.min(samples - stream * SAMPLES_PER_STREAM);
+                            (0..share)
+                                .filter(|_| {
+                                    let c = Complex {
+                                        re: lerp(SAMPLE_UPPER_LEFT.re, SAMPLE_LOWER_RIGHT.re, rng.next_f64()),
+                                        im: lerp(SAMPLE_LOWER_RIGHT.im, SAMPLE_UPPER_LEFT.im, rng.next_f64()),
+                                    };
+                                    escape_time(c, limit as usize).is_none()
+                                })
+                                .count() as u64
+                        })
+                        .sum::<u64>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+    .unwrap();
+
+    let sampled_area = 2.0
+        * (SAMPLE_LOWER_RIGHT.re - SAMPLE_UPPER_LEFT.re)
+        * (SAMPLE_UPPER_LEFT.im - SAMPLE_LOWER_RIGHT.im);
+    let p = hits as f64 / samples as f64;
+    let estimate = sampled_area * p;
+    let standard_error = sampled_area * (p * (1.0 - p) / samples as f64).sqrt();
+
+    AreaEstimate {
+        samples,
+        limit,
+        seed,
+        estimate,
+        standard_error,
+        confidence_95: [
+            estimate - 1.96 * standard_error,
+            estimate + 1.96 * standard_error,
+        ],
+        seconds: start.elapsed().as_secs_f64(),
+    }
+}
+
+fn lerp(from: f64, to: f64, t: f64) -> f64 {
+    from + (to - from) * t
+}
+
+/// Steele, Lea and Flood's SplitMix64: a tiny, fast generator that is
+/// plenty random enough for sampling.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed number in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn test_estimate_area() {
+    // The area of the set is about 1.5066.
+    let area = estimate_area(50_000, 500, 7, 2);
+    assert_eq!(area.samples, 50_000);
+    assert!(area.confidence_95[0] < area.estimate && area.estimate < area.confidence_95[1]);
+    assert!((area.estimate - 1.5066).abs() < 0.06, "estimate {}", area.estimate);
+
+    let again = estimate_area(50_000, 500, 7, 2);
+    assert_eq!(area.estimate, again.estimate);
+
+    // However many threads share the work, the same seed picks the same
+    // points.
+    let one = estimate_area(3 * SAMPLES_PER_STREAM + 5, 100, 7, 1);
+    for threads in [2, 3, 8] {
+        assert_eq!(estimate_area(3 * SAMPLES_PER_STREAM + 5, 100, 7, threads).estimate, one.estimate);
+    }
+}
+
+#[test]
+fn test_analyze_high_limit() {
+    // Every pixel of this viewport escapes at once, so the render is quick
+    // however high the limit is; the histogram mustn't depend on the limit.
+    // No samples for the area estimate, though, which would take forever.
+    let options = Options {
+        bounds: (4, 3),
+        upper_left: Complex { re: 2.0, im: 3.0 },
+        lower_right: Complex { re: 3.0, im: 2.0 },
+        limit: u32::MAX,
+        threads: 2,
+        ..Options::default()
+    };
+    let stats = analyze(&options, 0, 1);
+    assert_eq!(stats.interior_fraction, 0.0);
+    assert_eq!(stats.histogram.iter().map(|&(_, n)| n).sum::<u64>(), 12);
+}
@@ -0,0 +1,122 @@
+//! Check every rendering kernel against a set of small reference renders.
+//!
+//! The reference renders are listed in `tests/golden/renders.txt`; see the
+//! comment at the top of that file for how to regenerate them.
+
+use mandelbrot::palette::Palette;
+use mandelbrot::{KERNELS, parse_complex, parse_pair, write_image};
+use num::Complex;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct Golden {
+    name: String,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    limit: u32,
+    palette: Palette,
+    checksum: u64,
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn load_manifest() -> Vec<Golden> {
+    let text = fs::read_to_string(golden_dir().join("renders.txt")).expect("reading renders.txt");
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(fields.len(), 7, "malformed line in renders.txt: {}", line);
+            Golden {
+                name: fields[0].to_string(),
+                bounds: parse_pair(fields[1], 'x').expect("bad size"),
+                upper_left: parse_complex(fields[2]).expect("bad upper left corner"),
+                lower_right: parse_complex(fields[3]).expect("bad lower right corner"),
+                limit: fields[4].parse().expect("bad iteration limit"),
+                palette: fields[5].parse().expect("bad palette"),
+                checksum: u64::from_str_radix(fields[6], 16).expect("bad checksum"),
+            }
+        })
+        .collect()
+}
+
+/// 64-bit FNV-1a over the counts as little-endian bytes.
+fn checksum(counts: &[u32]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in counts.iter().flat_map(|count| count.to_le_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Rewrite `renders.txt` with fresh checksums, and regenerate the images.
+fn bless(goldens: &[Golden]) {
+    let manifest = golden_dir().join("renders.txt");
+    let text = fs::read_to_string(&manifest).unwrap();
+    let mut blessed = String::new();
+    for line in text.lines() {
+        let name = line.split_whitespace().next().unwrap_or("");
+        match goldens.iter().find(|g| !line.starts_with('#') && g.name == name) {
+            Some(golden) => {
+                let (render_name, render) = KERNELS[0];
+                let mut counts = vec![0; golden.bounds.0 * golden.bounds.1];
+                render(&mut counts, golden.bounds, golden.upper_left, golden.lower_right, golden.limit);
+                let (head, _) = line.trim_end().rsplit_once(char::is_whitespace).unwrap();
+                blessed.push_str(&format!("{}{:016x}\n", head, checksum(&counts)));
+
+                let image = golden_dir().join(format!("{}.png", golden.name));
+                let pixels = golden.palette.colorize(&counts, golden.limit);
+                write_image(image.to_str().unwrap(), &pixels, golden.bounds, golden.palette.color_type())
+                    .unwrap();
+                println!("blessed {} using the {} kernel", golden.name, render_name);
+            }
+            None => {
+                blessed.push_str(line);
+                blessed.push('\n');
+            }
+        }
+    }
+    fs::write(manifest, blessed).unwrap();
+}
+
+#[test]
+fn test_kernels_match_golden_renders() {
+    let goldens = load_manifest();
+    assert!(!goldens.is_empty());
+
+    if env::var_os("MANDELBROT_BLESS").is_some() {
+        bless(&goldens);
+        return;
+    }
+
+    for golden in &goldens {
+        let image = image::open(golden_dir().join(format!("{}.png", golden.name)))
+            .unwrap_or_else(|e| panic!("{}: can't read reference image: {}", golden.name, e));
+
+        for &(kernel_name, render) in KERNELS {
+            let mut counts = vec![0; golden.bounds.0 * golden.bounds.1];
+            render(&mut counts, golden.bounds, golden.upper_left, golden.lower_right, golden.limit);
+            assert_eq!(
+                format!("{:016x}", checksum(&counts)),
+                format!("{:016x}", golden.checksum),
+                "{}: escape counts from the {} kernel don't match",
+                golden.name,
+                kernel_name
+            );
+
+            let pixels = golden.palette.colorize(&counts, golden.limit);
+            assert!(
+                image.color() == golden.palette.color_type() && image.as_bytes() == &pixels[..],
+                "{}: image from the {} kernel doesn't match {}.png",
+                golden.name,
+                kernel_name,
+                golden.name
+            );
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use mandelbrot_parallel::cli::{
+    CliError, check_viewport, number, out_of_range, parse_corner, parse_palette, parse_size, split_flag,
+};
+use mandelbrot_parallel::palette::{PALETTE_NAMES, Palette};
+use num::Complex;
+
+/// Everything needed to render one image, as given on the command line.
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub bounds: (usize, usize),
+    pub upper_left: Complex<f64>,
+    pub lower_right: Complex<f64>,
+    pub limit: u32,
+    pub palette: Palette,
+    pub output: String,
+}
+
+/// What the user asked us to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Render(Options),
+    Help,
+}
+
+/// The options that take a value.
+enum Field {
+    Size,
+    UpperLeft,
+    LowerRight,
+    Iterations,
+    Palette,
+    Output,
+}
+
+impl Field {
+    fn from_flag(flag: &str) -> Option<Field> {
+        match flag {
+            "-s" | "--size" => Some(Field::Size),
+            "-u" | "--upper-left" => Some(Field::UpperLeft),
+            "-l" | "--lower-right" => Some(Field::LowerRight),
+            "-i" | "--iterations" => Some(Field::Iterations),
+            "-p" | "--palette" => Some(Field::Palette),
+            "-o" | "--output" => Some(Field::Output),
+            _ => None,
+        }
+    }
+}
+
+pub fn print_usage(program: &str) {
+    println!("Usage: {} [OPTIONS]", program);
+    println!();
+    println!("Render a region of the Mandelbrot set to a PNG file.");
+    println!();
+    println!("Options:");
+    println!("  -s, --size WxH             image size in pixels [default: 1000x750]");
+    println!("  -u, --upper-left RE,IM     upper left corner of the viewport [default: -2.0,1.125]");
+    println!("  -l, --lower-right RE,IM    lower right corner of the viewport [default: 1.0,-1.125]");
+    println!("  -i, --iterations N         iteration limit per point [default: 255]");
+    println!("  -p, --palette NAME         one of: {} [default: grayscale]", PALETTE_NAMES);
+    println!("  -o, --output FILE          PNG file to write [default: mandel.png]");
+    println!("  -h, --help                 print this message");
+    println!();
+    println!(
+        "Example: {} -o mandel.png -s 4000x3000 -u -1.20,0.35 -l -1,0.20",
+        program
+    );
+}
+
+/// Parse the command-line arguments, not including the program name.
+///
+/// Options may be written either as `--size 800x600` or `--size=800x600`.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    let mut options = Options {
+        bounds: (1000, 750),
+        upper_left: Complex { re: -2.0, im: 1.125 },
+        lower_right: Complex { re: 1.0, im: -1.125 },
+        limit: 255,
+        palette: Palette::Grayscale,
+        output: "mandel.png".to_string(),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(arg)?;
+
+        if flag == "-h" || flag == "--help" {
+            return Ok(Command::Help);
+        }
+        let field = match Field::from_flag(&flag) {
+            Some(field) => field,
+            None => return Err(CliError::UnknownFlag(flag)),
+        };
+
+        // Negative coordinates like `-1.20,0.35` look like flags, so the value
+        // of an option is always taken from the next argument verbatim.
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(CliError::MissingValue(flag)),
+        };
+
+        match field {
+            Field::Size => options.bounds = parse_size(&flag, &value)?,
+            Field::UpperLeft => options.upper_left = parse_corner(&flag, &value)?,
+            Field::LowerRight => options.lower_right = parse_corner(&flag, &value)?,
+            Field::Iterations => {
+                options.limit = number(&flag, &value)?;
+                if options.limit == 0 {
+                    return Err(out_of_range(&flag, &value, "must be at least 1"));
+                }
+            }
+            Field::Palette => options.palette = parse_palette(value)?,
+            Field::Output => options.output = value,
+        }
+    }
+
+    check_viewport(options.upper_left, options.lower_right)?;
+    Ok(Command::Render(options))
+}
+
+#[cfg(test)]
+use mandelbrot_parallel::ParseError;
+
+#[cfg(test)]
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_parse_args() {
+    let command = parse_args(args(&[
+        "-s", "400x300", "--upper-left=-1.20,0.35", "-l", "-1,0.20", "-i", "1000",
+        "-p", "fire", "-o", "out.png",
+    ]));
+    assert_eq!(
+        command,
+        Ok(Command::Render(Options {
+            bounds: (400, 300),
+            upper_left: Complex { re: -1.20, im: 0.35 },
+            lower_right: Complex { re: -1.0, im: 0.20 },
+            limit: 1000,
+            palette: Palette::Fire,
+            output: "out.png".to_string(),
+        }))
+    );
+    assert_eq!(parse_args(args(&["-s", "10x10", "--help"])), Ok(Command::Help));
+}
+
+#[test]
+fn test_parse_args_errors() {
+    assert_eq!(
+        parse_args(args(&["--size", "400y300"])),
+        Err(CliError::BadPair {
+            flag: "--size".to_string(),
+            value: "400y300".to_string(),
+            error: ParseError::MissingSeparator('x'),
+        })
+    );
+    assert_eq!(
+        parse_args(args(&["--colour", "red"])),
+        Err(CliError::UnknownFlag("--colour".to_string()))
+    );
+    assert_eq!(
+        parse_args(args(&["-o"])),
+        Err(CliError::MissingValue("-o".to_string()))
+    );
+    assert_eq!(
+        parse_args(args(&["mandel.png"])),
+        Err(CliError::UnexpectedArgument("mandel.png".to_string()))
+    );
+    assert!(matches!(
+        parse_args(args(&["-i", "0"])),
+        Err(CliError::OutOfRange { .. })
+    ));
+    assert!(matches!(
+        parse_args(args(&["-u", "1,1", "-l", "-1,-1"])),
+        Err(CliError::OutOfRange { .. })
+    ));
+    for size in ["100000x100000", "18446744073709551615x2"] {
+        assert!(matches!(
+            parse_args(args(&["-s", size])),
+            Err(CliError::OutOfRange { .. })
+        ));
+    }
+    assert!(parse_args(args(&["-s", "16384x16384"])).is_ok());
+}
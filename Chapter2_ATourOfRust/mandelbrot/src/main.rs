@@ -1,5 +1,7 @@
+mod cli;
+
+use cli::Command;
 use num::Complex;
-use std::str::FromStr;
 use image::ColorType;
 use image::png::PngEncoder;
 use std::fs::File;
@@ -7,15 +9,16 @@ use std::io;
 use std::env;
 
 // To build: cargo build --release
-// To perform an integration test:  time target/release/mandelbrot mandel.png 4000x3000 -1.20,0.35 -1,0.20
+// To perform an integration test:  time target/release/mandelbrot -o mandel.png -s 4000x3000 -u -1.20,0.35 -l -1,0.20
+// To see all the options:  target/release/mandelbrot --help
 
 /// Write the buffer `pixels` whose dimensions are given by `bounds` to the
-/// file named `filename`.
-fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), std::io::Error>{
+/// file named `filename`. `color_type` says how many bytes make up a pixel.
+fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize), color_type: ColorType) -> Result<(), std::io::Error>{
     let output = File::create(filename)?;
 
     let encoder = PngEncoder::new(output);
-    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, ColorType::L8).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, color_type).map_err(io::Error::other)?;
     Ok(())
 }
 
@@ -27,7 +30,6 @@ fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<
 /// the origin. If `c` seems to be a member (more precisely, if we reached the
 /// iteration limit without being able to prove that `c` is not a member),
 /// return `None`.
-
 fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
     let mut z = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
@@ -39,34 +41,6 @@ fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
     None
 }
 
-/// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0, 0.5"`.
-///
-/// Specifically `s` should have the form <left><sep><right>, where <sep> is
-/// the character given by the `separator` argument, and <left> and <right> are
-/// both strings that can be parsed by `T::from_str`. `separator` must be an
-/// ASCII character.
-///
-/// If `s` has the proper form, return `Some<(x,y)>`. If it doesn't parse
-/// correctly, return `None`
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
-    match s.find(separator) {
-        None => None,
-        Some(index) => match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-            (Ok(l), Ok(r)) => Some((l, r)),
-            _ => None,
-        },
-    }
-}
-
-/// Parse a pair of floating point numbers separated by a comma as a complex
-/// number.
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair(s, ',') {
-        Some((re, im)) => Some(Complex { re, im }),
-        None => None,
-    }
-}
-
 /// Given the row and column of a pixel in the output image, return the
 /// corresponding point on the complex plane.
 ///
@@ -91,72 +65,60 @@ fn pixel_to_point(
     }
 }
 
-/// Render a rectangle of the Mandelbrot set into a buffer of pixels.
+/// Render a rectangle of the Mandelbrot set into a buffer of escape counts.
 ///
-/// The `bounds` argument gives the width and height of the buffer `pixels`,
-/// which holds one grayscale pixel per byte. The `upper_left` and `lower_right`
-/// arguments specify points on the complex plane corresponding to the upper lef
-/// and lower right corners of the pixel buffer.
+/// The `bounds` argument gives the width and height of the buffer `counts`,
+/// which holds one escape count per pixel. The `upper_left` and `lower_right`
+/// arguments specify points on the complex plane corresponding to the upper left
+/// and lower right corners of the pixel buffer. Points that are still inside
+/// the circle after `limit` iterations get a count of `limit`.
 fn render(
-    pixels: &mut [u8],
+    counts: &mut [u32],
     bounds: (usize, usize),
     upper_left: Complex<f64>,
     lower_right: Complex<f64>,
+    limit: u32,
 ) {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(counts.len() == bounds.0 * bounds.1);
     for row in 0..bounds.1 {
         for column in 0..bounds.0 {
             let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
-            pixels[row * bounds.0 + column] =
-            match escape_time(point, 255) {
-                None => 0,
-                Some(count) => 255 - count as u8
+            counts[row * bounds.0 + column] =
+            match escape_time(point, limit as usize) {
+                None => limit,
+                Some(count) => count as u32
             };
         }
     }
 }
 
-
 fn main() {
-    let  args: Vec<String> = env::args().collect();
-    if args.len() != 5 {
-        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT", args[0]);
-        eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20", args[0]);
-        std::process::exit(1);
-    }
-
-    let bounds = parse_pair::<usize>(&args[2], 'x').expect("error parsing image dimensions");
-    let upper_left = parse_complex(&args[3]).expect("error parsing upper left corner point");
-    let lower_right = parse_complex(&args[4]).expect("error parsing lower right corner point");
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "mandelbrot".to_string());
+
+    let options = match cli::parse_args(args) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::Help) => {
+            cli::print_usage(&program);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Try '{} --help' for more information.", program);
+            std::process::exit(2);
+        }
+    };
 
-    let mut pixels = vec![0; bounds.0 * bounds.1];
+    let bounds = options.bounds;
+    let mut counts = vec![0; bounds.0 * bounds.1];
 
-    render(&mut pixels, bounds, upper_left, lower_right);
+    render(&mut counts, bounds, options.upper_left, options.lower_right, options.limit);
 
-    write_image(&args[1], &pixels, bounds).expect("error writing PNG file");
-}
-
-#[test]
-fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("", ','), None);
-    assert_eq!(parse_pair::<i32>("10,", ','), None);
-    assert_eq!(parse_pair::<i32>(",10", ','), None);
-    assert_eq!(parse_pair::<i32>("10,20", ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x", 'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
-}
-
-#[test]
-fn test_parse_complex() {
-    assert_eq!(
-        parse_complex("1.25,-0.0625"),
-        Some(Complex {
-            re: 1.25,
-            im: -0.0625
-        })
-    );
-    assert_eq!(parse_complex(", 0.0625"), None);
+    let pixels = options.palette.colorize(&counts, options.limit);
+    if let Err(e) = write_image(&options.output, &pixels, bounds, options.palette.color_type()) {
+        eprintln!("error: failed to write '{}': {}", options.output, e);
+        std::process::exit(1);
+    }
 }
 
 #[test]